@@ -0,0 +1,89 @@
+//! An endless run with clouds spawned and retired at a high rate,
+//! simulated with and without the cloud pool.
+//! The time of the rapier step is shown apart. So far the pool is not faster overall,
+//! the parked bodies still cost the step and the timings vary a lot from run to run.
+
+use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
+use montociel::*;
+use std::time::{Duration, Instant};
+
+const TICKS: u32 = 3000;
+const SEED: u64 = 1;
+
+/// Time spent in the physics step
+#[derive(Default)]
+struct StepTime {
+    started: Option<Instant>,
+    total: Duration,
+}
+
+fn start_step(mut step_time: ResMut<StepTime>) {
+    step_time.started = Some(Instant::now());
+}
+
+fn end_step(mut step_time: ResMut<StepTime>) {
+    if let Some(started) = step_time.started.take() {
+        step_time.total += started.elapsed();
+    }
+}
+
+fn simulate(name: &str, pool: CloudPool) {
+    let mut app = App::build();
+    app.insert_resource(Tuning {
+        new_cloud_count: 40,
+        new_cloud_period: 0.1,
+        new_cloud_min_speed: 10.,
+        new_cloud_max_speed: 20.,
+        play_area_radius: 600.,
+        // straight clouds only, each of them leaves the play area
+        cloud_patterns: PatternWeights {
+            straight: 1.,
+            orbit: 0.,
+            drift: 0.,
+            spiral: 0.,
+            homing: 0.,
+        },
+        ..Default::default()
+    })
+    .insert_resource(pool)
+    .add_plugin(HeadlessPlugin)
+    .init_resource::<StepTime>()
+    .add_system_to_stage(
+        SimulationStage,
+        start_step.system().before(PhysicsSystems::StepWorld),
+    )
+    .add_system_to_stage(
+        SimulationStage,
+        end_step.system().after(PhysicsSystems::StepWorld),
+    );
+    app.world_mut()
+        .get_resource_mut::<GameRng>()
+        .unwrap()
+        .set_fixed_seed(SEED);
+    let mut app = app.app;
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        app.update();
+    }
+    let elapsed = start.elapsed();
+
+    let step = app.world.get_resource::<StepTime>().unwrap().total;
+    let entities = app.world.query::<&Cloud>().iter(&app.world).count();
+    println!(
+        "{:<14} {} ticks in {:>8.2?} ({:>8.1} ticks/s), physics step {:>8.2?}, the rest {:>8.2?}, {} cloud entities",
+        name,
+        TICKS,
+        elapsed,
+        TICKS as f64 / elapsed.as_secs_f64(),
+        step,
+        elapsed - step,
+        entities
+    );
+}
+
+fn main() {
+    simulate("spawn/despawn", CloudPool::default());
+    simulate("pool", CloudPool::enabled());
+}
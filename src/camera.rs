@@ -0,0 +1,75 @@
+use crate::main_well;
+use crate::GravityWell;
+use crate::Montociel;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// how fast the camera catches up with the sheep, per second
+const FOLLOW_RATE: f32 = 4.;
+/// how fast the camera zooms in and out, per second
+const ZOOM_RATE: f32 = 2.;
+const MIN_ZOOM: f32 = 1.;
+const MAX_ZOOM: f32 = 3.;
+/// extra zoom at full speed
+const SPEED_ZOOM: f32 = 0.5;
+/// space kept around the main well when it is in frame, in pixels
+const WELL_MARGIN: f32 = 50.;
+
+/// The camera of the game world, the ui has its own
+pub struct MainCamera;
+
+/// Follow the sheep, zooming out as it flies away from the well it turns around or speeds up
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(follow_montociel.system());
+    }
+}
+
+fn follow_montociel(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    tuning: Res<Tuning>,
+    rapier_config: Res<RapierConfiguration>,
+    wells: Query<&GravityWell>,
+    montociel: Query<(&Transform, &RigidBodyVelocity), (With<Montociel>, Without<MainCamera>)>,
+    mut cameras: Query<&mut Transform, With<MainCamera>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let half_size = Vec2::new(window.width(), window.height()) / 2.;
+    // back to the earth when there is no sheep, in the menu
+    let (target, target_zoom) = match montociel.iter().next() {
+        Some((transform, vel)) => {
+            let target = transform.translation.truncate();
+            // zoom needed for the main well to fit on screen around the sheep
+            let scale = rapier_config.scale;
+            let (center, radius) = main_well(wells.iter(), &tuning, target / scale)
+                .map_or((Vec2::ZERO, tuning.earth_radius), |well| {
+                    (well.position * scale, well.radius * scale)
+                });
+            let well_extent = Vec2::splat(radius + WELL_MARGIN);
+            let fit_well = ((target - center).abs() + well_extent) / half_size;
+            let speed = vel.linvel.norm() / tuning.max_speed;
+            let zoom = f32::max(fit_well.max_element(), MIN_ZOOM + SPEED_ZOOM * speed);
+            (target, zoom.clamp(MIN_ZOOM, MAX_ZOOM))
+        }
+        None => (Vec2::ZERO, MIN_ZOOM),
+    };
+
+    // frame rate independent smoothing
+    let dt = time.delta_seconds();
+    let follow = 1. - f32::exp(-FOLLOW_RATE * dt);
+    let zoom_speed = 1. - f32::exp(-ZOOM_RATE * dt);
+    for mut transform in cameras.iter_mut() {
+        let position = transform.translation.truncate().lerp(target, follow);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        let zoom = transform.scale.x + (target_zoom - transform.scale.x) * zoom_speed;
+        transform.scale = Vec3::new(zoom, zoom, 1.);
+    }
+}
@@ -1,35 +1,238 @@
+use crate::on_tick;
+use crate::probability;
 use crate::AppState;
+use crate::CloudCollision;
+use crate::CloudKind;
+use crate::CloudPattern;
+use crate::Difficulty;
+use crate::GameRng;
+use crate::GravityWell;
+use crate::Level;
 use crate::Materials;
+use crate::PowerUps;
+use crate::RngReseed;
+use crate::RunEntity;
+use crate::Score;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
 use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier2d::physics::PhysicsSystems;
 use bevy_rapier2d::prelude::*;
+use std::time::Duration;
 extern crate rand;
 
-pub struct Cloud(Vec2);
+/// A cloud and its velocity
+pub struct Cloud(pub Vec2);
+/// Whether a cloud is flying or parked in the `CloudPool`
+pub struct InPlay(pub bool);
 struct NewCloudTimer(Timer);
 pub struct Evil;
+/// A planet or moon, see `GravityWell`
+pub struct Earth;
 pub struct CloudPlugin;
 
+/// Clouds out of play, kept to be reused rather than despawned and spawned again.
+/// A parked cloud stands still, hidden and without collisions.
+/// Off by default, the parked bodies cost the physics step more than the pool saves, see `cargo bench`.
+#[derive(Default)]
+pub struct CloudPool {
+    enabled: bool,
+    clouds: Vec<Entity>,
+    evil_clouds: Vec<Entity>,
+    /// both lists, a cloud can be retired twice in a tick before the commands are applied
+    parked: HashSet<Entity>,
+}
+
+/// Parked clouds of each kind, the physics step still pays a little for each of them
+const MAX_PARKED_CLOUDS: usize = 64;
+/// Where parked clouds wait, in physics units
+const PARKING_DISTANCE: f32 = 10000.;
+const PARKING_SPACING: f32 = 10.;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct CloudPoolReset;
+/// Taking clouds out of play
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct CloudRetire;
+/// Handing the velocity of each cloud to rapier
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CloudMotion;
+
+/// Number of clouds flying around, the earth aside
+pub const LIVE_CLOUDS: DiagnosticId =
+    DiagnosticId::from_u128(274944621662398983820253221150683594755);
+
 impl Plugin for CloudPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<NewCloudTimer>()
+            .init_resource::<CloudPool>()
+            .add_startup_system(setup_cloud_diagnostic.system())
+            .add_system(count_clouds.system())
             .add_system_set(
                 SystemSet::on_enter(AppState::InGame)
-                    .with_system(cloud_belt.system())
-                    .with_system(spawn_earth.system()),
+                    .with_system(reset_cloud_pool.system().label(CloudPoolReset))
+                    .with_system(cloud_belt.system().after(RngReseed).after(CloudPoolReset)),
             )
-            .add_system_set(
-                SystemSet::on_update(AppState::InGame)
-                    .with_system(cloud_kinematics.system())
-                    .with_system(newcloud_maker.system()),
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame)
+                    .with_system(
+                        cloud_kinematics
+                            .system()
+                            .label(CloudMotion)
+                            .before(PhysicsSystems::StepWorld),
+                    )
+                    .with_system(
+                        retire_far_clouds
+                            .system()
+                            .label(CloudRetire)
+                            .after(CloudCollision),
+                    )
+                    // the score of this tick sets the odds of evil clouds,
+                    // and the clouds retired this tick can be reused
+                    .with_system(
+                        newcloud_maker
+                            .system()
+                            .after(CloudCollision)
+                            .after(CloudRetire),
+                    ),
             )
-            .add_system_set(
-                SystemSet::on_update(AppState::GameOver)
-                    .with_system(cloud_kinematics.system())
-                    .with_system(newcloud_maker.system()),
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::GameOver)
+                    .with_system(
+                        cloud_kinematics
+                            .system()
+                            .label(CloudMotion)
+                            .before(PhysicsSystems::StepWorld),
+                    )
+                    .with_system(
+                        retire_far_clouds
+                            .system()
+                            .label(CloudRetire)
+                            .after(PhysicsSystems::StepWorld),
+                    )
+                    .with_system(
+                        newcloud_maker
+                            .system()
+                            .after(PhysicsSystems::StepWorld)
+                            .after(CloudRetire),
+                    ),
             );
     }
 }
 
+impl CloudPool {
+    /// A pool parking the clouds taken out of play rather than despawning them
+    pub fn enabled() -> Self {
+        CloudPool {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.clouds.len() + self.evil_clouds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn parked(&mut self, is_evil: bool) -> &mut Vec<Entity> {
+        if is_evil {
+            &mut self.evil_clouds
+        } else {
+            &mut self.clouds
+        }
+    }
+
+    /// Take a cloud out of play, once.
+    /// Only components the cloud already has are replaced, so it stays in the same archetype.
+    pub fn park(&mut self, commands: &mut Commands, entity: Entity, is_evil: bool) {
+        if self.parked.contains(&entity) {
+            return;
+        }
+        if !self.enabled || self.parked(is_evil).len() >= MAX_PARKED_CLOUDS {
+            commands.entity(entity).despawn();
+            return;
+        }
+        // a static body on its own, far from anything, still costs the physics step a little
+        let parked = self.parked(is_evil);
+        let spot = Vec2::new(
+            PARKING_DISTANCE + parked.len() as f32 * PARKING_SPACING,
+            if is_evil { PARKING_SPACING } else { 0. },
+        );
+        parked.push(entity);
+        self.parked.insert(entity);
+        commands
+            .entity(entity)
+            .insert(RigidBodyType::Static)
+            .insert(RigidBodyPosition::from(spot))
+            .insert(RigidBodyVelocity::default())
+            .insert(InPlay(false))
+            .insert(Cloud(Vec2::ZERO))
+            .insert(cloud_flags(false))
+            .insert(Visible {
+                is_visible: false,
+                is_transparent: true,
+            });
+    }
+
+    /// Put a parked cloud back in play, as a cloud of this kind
+    fn unpark(
+        &mut self,
+        commands: &mut Commands,
+        pos: Vec2,
+        vel: Vec2,
+        is_evil: bool,
+        kind: CloudKind,
+        pattern: CloudPattern,
+        material: Option<Handle<ColorMaterial>>,
+    ) -> Option<Entity> {
+        let entity = self.parked(is_evil).pop()?;
+        self.parked.remove(&entity);
+        let mut cloud = commands.entity(entity);
+        if let Some(material) = material {
+            cloud.insert(material);
+        }
+        cloud
+            .insert(RigidBodyType::KinematicVelocityBased)
+            .insert(RigidBodyPosition::from(pos))
+            .insert(RigidBodyVelocity {
+                linvel: vel.into(),
+                angvel: 0.0,
+            })
+            .insert(InPlay(true))
+            .insert(Cloud(vel))
+            .insert(kind)
+            .insert(pattern)
+            .insert(cloud_flags(true))
+            .insert(Visible {
+                is_visible: true,
+                is_transparent: true,
+            });
+        Some(entity)
+    }
+}
+
+/// Collider flags of a cloud, parked clouds collide with nothing
+fn cloud_flags(in_play: bool) -> ColliderFlags {
+    let groups = if in_play {
+        InteractionGroups::all()
+    } else {
+        InteractionGroups::none()
+    };
+    ColliderFlags {
+        collision_groups: groups,
+        solver_groups: groups,
+        active_events: ActiveEvents::INTERSECTION_EVENTS | ActiveEvents::CONTACT_EVENTS,
+        ..Default::default()
+    }
+}
+
 impl Default for NewCloudTimer {
     fn default() -> Self {
         NewCloudTimer(Timer::from_seconds(2., true))
@@ -39,99 +242,145 @@ impl Default for NewCloudTimer {
 fn newcloud_maker(
     mut commands: Commands,
     rapier_config: Res<RapierConfiguration>,
-    materials: Res<Materials>,
-    time: Res<Time>,
+    integration_parameters: Res<IntegrationParameters>,
+    materials: Option<Res<Materials>>,
+    difficulty: Res<Difficulty>,
+    score: Res<Score>,
+    tuning: Res<Tuning>,
+    level: Res<Level>,
+    mut rng: ResMut<GameRng>,
+    mut pool: ResMut<CloudPool>,
     mut timer: ResMut<NewCloudTimer>,
 ) {
-    if timer.0.tick(time.delta()).just_finished() {
+    if tuning.is_changed() {
+        timer
+            .0
+            .set_duration(Duration::from_secs_f32(tuning.new_cloud_period));
+    }
+    // tick with the physics step rather than the frame time to keep runs replayable
+    let dt = Duration::from_secs_f32(integration_parameters.dt);
+    if timer.0.tick(dt).just_finished() {
         use rand::Rng;
-        let mut rng = rand::thread_rng();
 
-        let nb = 4;
+        let nb = tuning.new_cloud_count;
         for _ in 0..nb {
+            let (center, size) = pick_well(&level, &rapier_config, &mut rng);
             let theta = rng.gen_range(0.0..10000.) * 2. * std::f32::consts::PI / nb as f32;
-            let rho = 1.;
-            let speed = rng.gen_range(1.0..5.0);
-            let pos = Vec2::new(f32::cos(theta), f32::sin(theta)) * rho;
-            let vel = Vec2::new(pos.x, pos.y).normalize() * speed;
-            let is_evil = false;
-            spawn_cloud(&mut commands, &rapier_config, &materials, pos, vel, is_evil);
+            let speed = rng.gen_range(tuning.new_cloud_min_speed..tuning.new_cloud_max_speed);
+            let is_evil = rng.gen_bool(difficulty.evil_chance(&tuning, &score) as f64);
+            let kind = if is_evil {
+                CloudKind::Plain
+            } else {
+                CloudKind::roll(&mut rng, tuning.power_up_chance)
+            };
+            let pattern =
+                CloudPattern::roll(&mut rng, &tuning, &rapier_config, center, size, is_evil);
+            let rho = pattern
+                .spawn_distance(&tuning, &rapier_config)
+                .unwrap_or(tuning.new_cloud_distance)
+                * size;
+            let offset = Vec2::new(f32::cos(theta), f32::sin(theta)) * rho;
+            let pos = center + offset;
+            let vel = offset.normalize() * speed;
+            spawn_cloud(
+                &mut commands,
+                &mut pool,
+                &rapier_config,
+                materials.as_deref(),
+                &tuning,
+                pos,
+                vel,
+                is_evil,
+                kind,
+                pattern,
+            );
         }
     }
 }
 
+/// Center of a well to spawn a cloud around, in physics units, and its size
+fn pick_well(level: &Level, rapier_config: &RapierConfiguration, rng: &mut GameRng) -> (Vec2, f32) {
+    use rand::Rng;
+
+    let index = if level.wells.len() > 1 {
+        rng.gen_range(0..level.wells.len())
+    } else {
+        0
+    };
+    match level.wells.get(index) {
+        Some(well) => (well.position / rapier_config.scale, well.size),
+        None => (Vec2::ZERO, 1.),
+    }
+}
+
 fn cloud_belt(
     mut commands: Commands,
     rapier_config: Res<RapierConfiguration>,
-    materials: Res<Materials>,
+    materials: Option<Res<Materials>>,
+    tuning: Res<Tuning>,
+    level: Res<Level>,
+    mut rng: ResMut<GameRng>,
+    mut pool: ResMut<CloudPool>,
 ) {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
 
-    let nb = 10;
+    let nb = tuning.cloud_belt_count;
     for i in 0..nb {
+        let (center, size) = pick_well(&level, &rapier_config, &mut rng);
         let theta = i as f32 * 2. * std::f32::consts::PI / nb as f32;
-        let rho = 10.;
-        let speed = rng.gen_range(0.0..5.0);
-        let pos = Vec2::new(f32::cos(theta), f32::sin(theta)) * rho;
-        let vel = Vec2::new(pos.x, pos.y).normalize() * speed;
-        let is_evil = false;
-        spawn_cloud(&mut commands, &rapier_config, &materials, pos, vel, is_evil);
+        let speed = rng.gen_range(0.0..tuning.cloud_belt_max_speed);
+        // the run just started, only the base chance applies
+        let is_evil = rng.gen_bool(probability(tuning.base_evil_chance) as f64);
+        let kind = if is_evil {
+            CloudKind::Plain
+        } else {
+            CloudKind::roll(&mut rng, tuning.power_up_chance)
+        };
+        let pattern = CloudPattern::roll(&mut rng, &tuning, &rapier_config, center, size, is_evil);
+        let rho = pattern
+            .spawn_distance(&tuning, &rapier_config)
+            .unwrap_or(tuning.cloud_belt_distance)
+            * size;
+        let offset = Vec2::new(f32::cos(theta), f32::sin(theta)) * rho;
+        let pos = center + offset;
+        let vel = offset.normalize() * speed;
+        spawn_cloud(
+            &mut commands,
+            &mut pool,
+            &rapier_config,
+            materials.as_deref(),
+            &tuning,
+            pos,
+            vel,
+            is_evil,
+            kind,
+            pattern,
+        );
     }
 }
 
-fn spawn_earth(
-    mut commands: Commands,
-    rapier_config: Res<RapierConfiguration>,
-    materials: Res<Materials>,
-) {
-    //TODO sapwn mother earth the root of all evil
-    let radius = 60. / rapier_config.scale;
-    let rigid_body = RigidBodyBundle {
-        body_type: RigidBodyType::Static,
-        position: Vec2::new(0., 0.).into(),
-        velocity: RigidBodyVelocity {
-            linvel: Vec2::new(0., 0.).into(),
-            angvel: 0.0,
-        },
-        ..Default::default()
-    };
-    let collider = ColliderBundle {
-        shape: ColliderShape::ball(radius),
-        mass_properties: ColliderMassProps::Density(1.0),
-        material: ColliderMaterial {
-            restitution: 1.0,
-            ..Default::default()
-        },
-        flags: (ActiveEvents::INTERSECTION_EVENTS | ActiveEvents::CONTACT_EVENTS).into(),
-        ..Default::default()
-    };
-    commands
-        .spawn_bundle(rigid_body)
-        .insert_bundle(collider)
-        .insert_bundle(SpriteBundle {
-            material: materials.earth_material.clone(),
-            sprite: Sprite::new(Vec2::new(
-                2. * radius * rapier_config.scale,
-                2. * radius * rapier_config.scale,
-            )),
-            ..Default::default()
-        })
-        .insert(RigidBodyPositionSync::Discrete)
-        .insert(Cloud(Vec2::new(0., 0.)))
-        .insert(Evil);
-}
-
 fn spawn_cloud(
     commands: &mut Commands,
+    pool: &mut CloudPool,
     rapier_config: &Res<RapierConfiguration>,
-    materials: &Res<Materials>,
+    materials: Option<&Materials>,
+    tuning: &Tuning,
     pos: Vec2,
     vel: Vec2,
     is_evil: bool,
+    kind: CloudKind,
+    pattern: CloudPattern,
 ) {
+    // no sprite when running headless
+    let material = materials.map(|materials| materials.cloud(is_evil, kind).clone());
+    if pool
+        .unpark(commands, pos, vel, is_evil, kind, pattern, material.clone())
+        .is_some()
+    {
+        return;
+    }
     //Spawn a cloud
-    let radius = 15. / rapier_config.scale;
+    let radius = tuning.cloud_radius / rapier_config.scale;
     let rigid_body = RigidBodyBundle {
         body_type: RigidBodyType::KinematicVelocityBased,
         position: pos.into(),
@@ -148,43 +397,90 @@ fn spawn_cloud(
             restitution: 1.0,
             ..Default::default()
         },
-        flags: (ActiveEvents::INTERSECTION_EVENTS | ActiveEvents::CONTACT_EVENTS).into(),
+        flags: cloud_flags(true),
         ..Default::default()
     };
+    let mut cloud = commands.spawn_bundle(rigid_body);
+    cloud
+        .insert_bundle(collider)
+        .insert(RigidBodyPositionSync::Discrete)
+        .insert(Cloud(vel))
+        .insert(InPlay(true))
+        .insert(kind)
+        .insert(pattern)
+        .insert(RunEntity)
+        // part of the sprite bundle, headless clouds have it too so parking them is the same
+        .insert(Visible::default());
     if is_evil {
-        commands
-            .spawn_bundle(rigid_body)
-            .insert_bundle(collider)
-            .insert_bundle(SpriteBundle {
-                material: materials.cloud_material.clone(),
-                sprite: Sprite::new(Vec2::new(
-                    2. * radius * rapier_config.scale,
-                    2. * radius * rapier_config.scale,
-                )),
-                ..Default::default()
-            })
-            .insert(RigidBodyPositionSync::Discrete)
-            .insert(Cloud(vel))
-            .insert(Evil);
-    } else {
-        commands
-            .spawn_bundle(rigid_body)
-            .insert_bundle(collider)
-            .insert_bundle(SpriteBundle {
-                material: materials.cloud_material.clone(),
-                sprite: Sprite::new(Vec2::new(
-                    2. * radius * rapier_config.scale,
-                    2. * radius * rapier_config.scale,
-                )),
-                ..Default::default()
-            })
-            .insert(RigidBodyPositionSync::Discrete)
-            .insert(Cloud(vel));
+        cloud.insert(Evil);
+    }
+    if let Some(material) = material {
+        cloud.insert_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(Vec2::new(
+                2. * radius * rapier_config.scale,
+                2. * radius * rapier_config.scale,
+            )),
+            ..Default::default()
+        });
     }
 }
 
-fn cloud_kinematics(mut velocities: Query<(&Cloud, &mut RigidBodyVelocity)>) {
+/// Clouds fly away from the wells in the end, forget them once they leave the play area
+fn retire_far_clouds(
+    mut commands: Commands,
+    mut pool: ResMut<CloudPool>,
+    rapier_config: Res<RapierConfiguration>,
+    tuning: Res<Tuning>,
+    wells: Query<&GravityWell>,
+    clouds: Query<(Entity, &RigidBodyPosition, &InPlay, Option<&Evil>), With<Cloud>>,
+) {
+    let max_distance = tuning.play_area_radius / rapier_config.scale;
+    for (entity, pos, in_play, evil) in clouds.iter() {
+        let position: Vec2 = pos.position.translation.vector.into();
+        // the play area surrounds each well
+        let distance = wells
+            .iter()
+            .map(|well| position.distance(well.position))
+            .reduce(f32::min)
+            .unwrap_or_else(|| position.length());
+        if in_play.0 && distance > max_distance {
+            pool.park(&mut commands, entity, evil.is_some());
+        }
+    }
+}
+
+/// The clouds of the last run are gone with it
+fn reset_cloud_pool(mut pool: ResMut<CloudPool>) {
+    pool.clouds.clear();
+    pool.evil_clouds.clear();
+    pool.parked.clear();
+}
+
+fn setup_cloud_diagnostic(diagnostics: Option<ResMut<Diagnostics>>) {
+    // headless apps have no diagnostics
+    if let Some(mut diagnostics) = diagnostics {
+        diagnostics.add(Diagnostic::new(LIVE_CLOUDS, "live_clouds", 20));
+    }
+}
+
+fn count_clouds(diagnostics: Option<ResMut<Diagnostics>>, clouds: Query<&InPlay, With<Cloud>>) {
+    if let Some(mut diagnostics) = diagnostics {
+        let live_clouds = clouds.iter().filter(|in_play| in_play.0).count();
+        diagnostics.add_measurement(LIVE_CLOUDS, live_clouds as f64);
+    }
+}
+
+fn cloud_kinematics(
+    power_ups: Res<PowerUps>,
+    mut velocities: Query<(&Cloud, &mut RigidBodyVelocity)>,
+) {
+    let speed_factor = power_ups.cloud_speed_factor();
     for (vel, mut next_vel) in velocities.iter_mut() {
-        next_vel.linvel = vel.0.into();
+        let vel = vel.0 * speed_factor;
+        // only touch the velocity when needed, each change is synced back to rapier
+        if Vec2::from(next_vel.linvel) != vel {
+            next_vel.linvel = vel.into();
+        }
     }
 }
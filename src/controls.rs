@@ -0,0 +1,226 @@
+use bevy::input::gamepad::{Gamepad, GamepadButton, GamepadEvent, GamepadEventType};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// What the player can do with the sheep
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    ThrustClockwise,
+    ThrustCounterClockwise,
+    Brake,
+    Dash,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [
+        Action::ThrustClockwise,
+        Action::ThrustCounterClockwise,
+        Action::Brake,
+        Action::Dash,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::ThrustClockwise => "Thrust clockwise",
+            Action::ThrustCounterClockwise => "Thrust counter-clockwise",
+            Action::Brake => "Brake",
+            Action::Dash => "Dash",
+        }
+    }
+
+    /// name of the action in headless input scripts
+    pub fn script_name(self) -> &'static str {
+        match self {
+            Action::ThrustClockwise => "cw",
+            Action::ThrustCounterClockwise => "ccw",
+            Action::Brake => "brake",
+            Action::Dash => "dash",
+        }
+    }
+
+    /// bit of the action in `PlayerInput`
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A key or button triggering an action
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Gamepad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    fn same_device(self, other: Binding) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    /// The first key or button pressed this frame, if any
+    pub fn just_pressed(devices: &InputDevices) -> Option<Binding> {
+        if let Some(key) = devices.keys.get_just_pressed().next() {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = devices.mouse.get_just_pressed().next() {
+            return Some(Binding::Mouse(*button));
+        }
+        devices
+            .gamepad_buttons
+            .get_just_pressed()
+            .find(|GamepadButton(gamepad, _)| devices.gamepads.0.contains(gamepad))
+            .map(|GamepadButton(_, button)| Binding::Gamepad(*button))
+    }
+}
+
+/// Keys and buttons bound to each action
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bindings(pub Vec<(Action, Binding)>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        Bindings(vec![
+            (ThrustClockwise, Binding::Key(KeyCode::D)),
+            (ThrustClockwise, Binding::Key(KeyCode::Right)),
+            (ThrustClockwise, Binding::Mouse(MouseButton::Left)),
+            (
+                ThrustClockwise,
+                Binding::Gamepad(GamepadButtonType::DPadRight),
+            ),
+            (ThrustCounterClockwise, Binding::Key(KeyCode::A)),
+            (ThrustCounterClockwise, Binding::Key(KeyCode::Left)),
+            (ThrustCounterClockwise, Binding::Mouse(MouseButton::Right)),
+            (
+                ThrustCounterClockwise,
+                Binding::Gamepad(GamepadButtonType::DPadLeft),
+            ),
+            (Brake, Binding::Key(KeyCode::S)),
+            (Brake, Binding::Key(KeyCode::Down)),
+            (Brake, Binding::Gamepad(GamepadButtonType::LeftTrigger2)),
+            (Dash, Binding::Key(KeyCode::Space)),
+            (Dash, Binding::Mouse(MouseButton::Middle)),
+            (Dash, Binding::Gamepad(GamepadButtonType::South)),
+        ])
+    }
+}
+
+/// Gamepads currently plugged in
+#[derive(Default)]
+pub struct ConnectedGamepads(HashSet<Gamepad>);
+
+/// Devices the bindings are read from
+pub struct InputDevices<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse: &'a Input<MouseButton>,
+    pub gamepad_buttons: &'a Input<GamepadButton>,
+    pub gamepads: &'a ConnectedGamepads,
+}
+
+impl Bindings {
+    fn is_pressed(binding: Binding, devices: &InputDevices) -> bool {
+        match binding {
+            Binding::Key(key) => devices.keys.pressed(key),
+            Binding::Mouse(button) => devices.mouse.pressed(button),
+            Binding::Gamepad(button) => devices.gamepads.0.iter().any(|gamepad| {
+                devices
+                    .gamepad_buttons
+                    .pressed(GamepadButton(*gamepad, button))
+            }),
+        }
+    }
+
+    pub fn of(&self, action: Action) -> impl Iterator<Item = Binding> + '_ {
+        self.0
+            .iter()
+            .filter(move |(bound_action, _)| *bound_action == action)
+            .map(|(_, binding)| *binding)
+    }
+
+    /// Bind an action, replacing its bindings on the same device.
+    /// A binding is only used by one action, returns the action it was taken from.
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Option<Action> {
+        let conflict = self
+            .0
+            .iter()
+            .find(|(bound_action, bound)| *bound == binding && *bound_action != action)
+            .map(|(bound_action, _)| *bound_action);
+        self.0.retain(|(bound_action, bound)| {
+            *bound != binding && !(*bound_action == action && bound.same_device(binding))
+        });
+        self.0.push((action, binding));
+        conflict
+    }
+
+    /// Bits of the actions held on the devices
+    pub fn read(&self, devices: &InputDevices) -> u8 {
+        let mut bits = 0;
+        for (action, binding) in &self.0 {
+            if Bindings::is_pressed(*binding, devices) {
+                bits |= action.bit();
+            }
+        }
+        bits
+    }
+}
+
+/// Actions held by the player during the current tick
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlayerInput {
+    pressed: u8,
+    last_pressed: u8,
+}
+
+impl PlayerInput {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed & action.bit() != 0
+    }
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.last_pressed & action.bit() == 0
+    }
+    /// Move on to the next tick with these actions held
+    pub fn update(&mut self, bits: u8) {
+        self.last_pressed = self.pressed;
+        self.pressed = bits;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct PlayerInputLabel;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Bindings>()
+            .init_resource::<PlayerInput>()
+            .init_resource::<ConnectedGamepads>()
+            .add_system_to_stage(CoreStage::PreUpdate, track_gamepads.system());
+    }
+}
+
+fn track_gamepads(
+    mut gamepads: ResMut<ConnectedGamepads>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                gamepads.0.insert(*gamepad);
+            }
+            GamepadEventType::Disconnected => {
+                gamepads.0.remove(gamepad);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::CloudCollision;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
+use bevy_rapier2d::prelude::*;
+
+/// The dash of the sheep, a burst of speed along the tangent going over
+/// `Tuning::max_speed` for a moment
+pub struct Dash {
+    /// spent by dashing and refilled by bouncing on clouds, from 0 to 1
+    pub stamina: f32,
+    /// seconds since the last dash
+    pub since_dash: f32,
+}
+
+pub struct DashPlugin;
+
+impl Default for Dash {
+    fn default() -> Self {
+        Dash {
+            stamina: 1.,
+            since_dash: f32::INFINITY,
+        }
+    }
+}
+
+impl Dash {
+    /// Cooled down, with enough stamina left
+    pub fn is_ready(&self, tuning: &Tuning) -> bool {
+        self.since_dash >= tuning.dash_cooldown && self.stamina >= tuning.dash_cost
+    }
+
+    pub fn is_boosting(&self, tuning: &Tuning) -> bool {
+        self.since_dash < tuning.dash_duration
+    }
+
+    pub fn max_speed(&self, tuning: &Tuning) -> f32 {
+        if self.is_boosting(tuning) {
+            tuning.dash_max_speed
+        } else {
+            tuning.max_speed
+        }
+    }
+
+    /// Spend the stamina of a dash
+    pub fn start(&mut self, tuning: &Tuning) {
+        self.stamina -= tuning.dash_cost;
+        self.since_dash = 0.;
+    }
+
+    /// Stamina given back by a cloud bounce
+    pub fn refill(&mut self, tuning: &Tuning) {
+        self.stamina = f32::min(self.stamina + tuning.dash_refill, 1.);
+    }
+}
+
+impl Plugin for DashPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set_to_stage(
+            SimulationStage,
+            on_tick(AppState::InGame).with_system(
+                tick_dash
+                    .system()
+                    .after(PhysicsSystems::StepWorld)
+                    .before(CloudCollision),
+            ),
+        );
+    }
+}
+
+fn tick_dash(integration_parameters: Res<IntegrationParameters>, mut dashes: Query<&mut Dash>) {
+    for mut dash in dashes.iter_mut() {
+        dash.since_dash += integration_parameters.dt;
+    }
+}
@@ -0,0 +1,78 @@
+use crate::LIVE_CLOUDS;
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+/// Frame rate and live cloud count, toggled with F3
+pub struct DebugPlugin;
+struct DebugUI;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_system(toggle_debug_ui.system())
+            .add_system(update_debug_ui.system());
+    }
+}
+
+fn toggle_debug_ui(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    query: Query<Entity, With<DebugUI>>,
+) {
+    if !keys.just_pressed(KeyCode::F3) {
+        return;
+    }
+    if let Some(entity) = query.iter().next() {
+        commands.entity(entity).despawn_recursive();
+        return;
+    }
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // ui y axis points up, this is the top right corner
+                position: Rect {
+                    bottom: Val::Px(20.0),
+                    right: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: String::new(),
+                        style: style.clone(),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style,
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(DebugUI);
+}
+
+fn update_debug_ui(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, With<DebugUI>>) {
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.average())
+        .unwrap_or_default();
+    let clouds = diagnostics
+        .get(LIVE_CLOUDS)
+        .and_then(|clouds| clouds.value())
+        .unwrap_or_default();
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("fps: {:.0}\n", fps);
+        text.sections[1].value = format!("clouds: {}", clouds);
+    }
+}
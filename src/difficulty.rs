@@ -0,0 +1,61 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::Score;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
+use bevy_rapier2d::prelude::*;
+
+/// Chance for a newly spawned cloud to be evil.
+/// It ramps linearly with the score and the time spent in the current run, as set in `Tuning`,
+/// and is capped so there are always some clouds left to bounce on.
+#[derive(Default)]
+pub struct Difficulty {
+    /// seconds elapsed since the start of the run
+    pub elapsed: f32,
+}
+pub struct DifficultyPlugin;
+
+impl Difficulty {
+    pub fn evil_chance(&self, tuning: &Tuning, score: &Score) -> f32 {
+        let chance = tuning.base_evil_chance
+            + tuning.evil_chance_per_point * score.get() as f32
+            + tuning.evil_chance_per_second * self.elapsed;
+        probability(chance).min(probability(tuning.max_evil_chance))
+    }
+}
+
+/// Between 0 and 1, 0 for NaN, so it can go into `gen_bool`
+pub fn probability(value: f32) -> f32 {
+    if value.is_nan() {
+        0.
+    } else {
+        value.clamp(0., 1.)
+    }
+}
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Difficulty>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InGame).with_system(reset_difficulty.system()),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame)
+                    .with_system(ramp_difficulty.system().before(PhysicsSystems::StepWorld)),
+            );
+    }
+}
+
+fn reset_difficulty(mut difficulty: ResMut<Difficulty>) {
+    difficulty.elapsed = 0.;
+}
+
+fn ramp_difficulty(
+    integration_parameters: Res<IntegrationParameters>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    difficulty.elapsed += integration_parameters.dt;
+}
@@ -0,0 +1,263 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::Args;
+use crate::Cloud;
+use crate::Earth;
+use crate::Evil;
+use crate::Materials;
+use crate::Montociel;
+use crate::RunEntity;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+/// How the pull of a well weakens away from its surface
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum GravityLaw {
+    /// the same pull at any distance
+    Constant,
+    /// pull divided by the distance to the center
+    InverseLinear,
+    /// pull divided by the squared distance to the center,
+    /// `softening` in radii keeps it from blowing up close to the center
+    InverseSquare { softening: f32 },
+}
+
+/// A planet or moon pulling the sheep.
+/// The pull is `strength` times `Tuning::gravity` at the surface, and follows `law` further away.
+pub struct GravityWell {
+    /// center, in physics units
+    pub position: Vec2,
+    pub strength: f32,
+    /// `Tuning::gravity_law` when none
+    pub law: Option<GravityLaw>,
+    /// in physics units
+    pub radius: f32,
+    /// ends the run on contact, as the earth does
+    pub lethal: bool,
+}
+
+/// A gravity well of a level
+#[derive(Clone, Debug)]
+pub struct WellLayout {
+    /// in pixels
+    pub position: Vec2,
+    /// radius in earth radii, see `Tuning::earth_radius`
+    pub size: f32,
+    pub strength: f32,
+    /// `Tuning::gravity_law` when none
+    pub law: Option<GravityLaw>,
+    pub lethal: bool,
+}
+
+/// The gravity wells of a run, clouds spawn around each of them.
+/// Picked with `--level <name>`, the earth alone by default.
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub wells: Vec<WellLayout>,
+}
+
+pub struct GravityPlugin;
+
+impl Level {
+    pub const NAMES: [&'static str; 3] = ["earth", "moon", "binary"];
+
+    pub fn named(name: &str) -> Option<Self> {
+        let wells = match name {
+            // the earth pulls just as hard wherever the sheep is
+            "earth" => vec![WellLayout {
+                position: Vec2::ZERO,
+                size: 1.,
+                strength: 1.,
+                law: None,
+                lethal: true,
+            }],
+            // a moon to land on, its pull fading away from it
+            "moon" => vec![
+                WellLayout {
+                    position: Vec2::ZERO,
+                    size: 1.,
+                    strength: 1.,
+                    law: None,
+                    lethal: true,
+                },
+                WellLayout {
+                    position: Vec2::new(600., 0.),
+                    size: 0.5,
+                    strength: 2.,
+                    law: Some(GravityLaw::InverseSquare { softening: 0.5 }),
+                    lethal: false,
+                },
+            ],
+            // two earths with the sheep in between
+            "binary" => vec![
+                WellLayout {
+                    position: Vec2::new(-400., -250.),
+                    size: 1.,
+                    strength: 1.,
+                    law: Some(GravityLaw::InverseLinear),
+                    lethal: true,
+                },
+                WellLayout {
+                    position: Vec2::new(400., 250.),
+                    size: 1.,
+                    strength: 1.,
+                    law: Some(GravityLaw::InverseLinear),
+                    lethal: true,
+                },
+            ],
+            _ => return None,
+        };
+        Some(Level { wells })
+    }
+}
+
+impl FromWorld for Level {
+    fn from_world(world: &mut World) -> Self {
+        let name = world
+            .get_resource::<Args>()
+            .and_then(|args| args.level.clone())
+            .unwrap_or_else(|| "earth".to_string());
+        Level::named(&name).unwrap_or_else(|| {
+            eprintln!(
+                "unknown level {}, expected one of {}",
+                name,
+                Level::NAMES.join(", ")
+            );
+            Level::named("earth").unwrap()
+        })
+    }
+}
+
+impl GravityLaw {
+    /// Pull relative to the one at the surface, at this distance from the center in radii
+    pub fn factor(&self, distance: f32) -> f32 {
+        match *self {
+            GravityLaw::Constant => 1.,
+            GravityLaw::InverseLinear => 1. / distance,
+            GravityLaw::InverseSquare { softening } => {
+                let softening2 = softening * softening;
+                (1. + softening2) / (distance * distance + softening2)
+            }
+        }
+    }
+}
+
+impl GravityWell {
+    /// Pull on a body of this mass at this position, in physics units
+    pub fn pull(&self, tuning: &Tuning, mass: f32, position: Vec2) -> Vec2 {
+        let offset = self.position - position;
+        let distance = offset.length();
+        let eps = 0.0001;
+        let law = self.law.unwrap_or(tuning.gravity_law);
+        let magnitude = tuning.gravity * self.strength * mass * law.factor(distance / self.radius);
+        offset / (distance + eps) * magnitude
+    }
+}
+
+impl Plugin for GravityPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Level>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_wells.system()))
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame)
+                    .with_system(update_forces.system().before(PhysicsSystems::StepWorld)),
+            );
+    }
+}
+
+fn spawn_wells(
+    mut commands: Commands,
+    rapier_config: Res<RapierConfiguration>,
+    materials: Option<Res<Materials>>,
+    tuning: Res<Tuning>,
+    level: Res<Level>,
+) {
+    for layout in &level.wells {
+        let position = layout.position / rapier_config.scale;
+        let radius = layout.size * tuning.earth_radius / rapier_config.scale;
+        let rigid_body = RigidBodyBundle {
+            body_type: RigidBodyType::Static,
+            position: position.into(),
+            ..Default::default()
+        };
+        let collider = ColliderBundle {
+            shape: ColliderShape::ball(radius),
+            mass_properties: ColliderMassProps::Density(1.0),
+            // as bouncy as the earth always was
+            material: ColliderMaterial {
+                restitution: 1.0,
+                ..Default::default()
+            },
+            flags: (ActiveEvents::INTERSECTION_EVENTS | ActiveEvents::CONTACT_EVENTS).into(),
+            ..Default::default()
+        };
+        let mut well = commands.spawn_bundle(rigid_body);
+        well.insert_bundle(collider)
+            .insert(RigidBodyPositionSync::Discrete)
+            .insert(GravityWell {
+                position,
+                strength: layout.strength,
+                law: layout.law,
+                radius,
+                lethal: layout.lethal,
+            })
+            .insert(Earth)
+            .insert(RunEntity);
+        // touching a lethal well kills like an evil cloud, the others are solid ground
+        if layout.lethal {
+            well.insert(Cloud(Vec2::ZERO)).insert(Evil);
+        }
+        if let Some(materials) = &materials {
+            let material = if layout.lethal {
+                &materials.earth_material
+            } else {
+                &materials.moon_material
+            };
+            well.insert_bundle(SpriteBundle {
+                material: material.clone(),
+                sprite: Sprite::new(Vec2::splat(2. * radius * rapier_config.scale)),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// The well pulling hardest at this position, the one the sheep turns around
+pub fn main_well<'a>(
+    wells: impl Iterator<Item = &'a GravityWell>,
+    tuning: &Tuning,
+    position: Vec2,
+) -> Option<&'a GravityWell> {
+    wells.max_by(|a, b| {
+        let pull_a = a.pull(tuning, 1., position).length();
+        let pull_b = b.pull(tuning, 1., position).length();
+        // a well with a NaN pull, like one of radius 0, must not crash the game
+        pull_a.total_cmp(&pull_b)
+    })
+}
+
+fn update_forces(
+    tuning: Res<Tuning>,
+    wells: Query<&GravityWell>,
+    mut rigid_bodies: Query<
+        (
+            &mut RigidBodyForces,
+            &RigidBodyPosition,
+            &RigidBodyMassProps,
+        ),
+        With<Montociel>,
+    >,
+) {
+    for (mut rb_forces, rb_pos, rb_mass) in rigid_bodies.iter_mut() {
+        let position = rb_pos.position.translation.vector.into();
+        let gravity = wells.iter().fold(Vec2::ZERO, |force, well| {
+            force + well.pull(&tuning, rb_mass.mass(), position)
+        });
+        rb_forces.force = gravity.into();
+    }
+}
@@ -0,0 +1,149 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::Args;
+use crate::Cloud;
+use crate::DeathCause;
+use crate::GameRng;
+use crate::GameplayPlugins;
+use crate::InPlay;
+use crate::Replay;
+use crate::ReplaySession;
+use crate::RunStats;
+use crate::Score;
+use crate::SimulationClock;
+use crate::SimulationPlugin;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::app::AppExit;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+
+/// The gameplay without window nor rendering, starting right in a run.
+/// `Args` and `Tuning` default unless they are inserted before.
+pub struct HeadlessPlugin;
+
+/// Progress of a headless run
+struct HeadlessRun {
+    ticks: u32,
+    max_ticks: u32,
+    reported: bool,
+}
+
+/// Simulate a run without window nor rendering, then print how it went.
+/// The inputs come from `--script` or `--replay`, the sheep is left alone otherwise.
+pub fn run_headless(args: Args) {
+    let mut app = App::build();
+
+    if let Some(script) = &args.script {
+        let seed = args.seed.unwrap_or_else(rand::random);
+        match Replay::from_script(seed, script) {
+            Ok(replay) => {
+                app.insert_resource(ReplaySession::playback(replay));
+            }
+            Err(e) => {
+                eprintln!("bad input script: {}", e);
+                return;
+            }
+        }
+    }
+
+    let tuning = Tuning::read_file().unwrap_or_else(|e| {
+        eprintln!("{}, using the default tuning", e);
+        Tuning::default()
+    });
+
+    app.insert_resource(HeadlessRun {
+        ticks: 0,
+        max_ticks: args.headless.unwrap_or_default(),
+        reported: false,
+    })
+    .insert_resource(args)
+    .insert_resource(tuning)
+    .add_plugin(HeadlessPlugin)
+    .add_system_set_to_stage(
+        SimulationStage,
+        on_tick(AppState::InGame).with_system(count_ticks.system()),
+    )
+    .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(game_over.system()));
+
+    app.run();
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Args>()
+            .init_resource::<Tuning>()
+            .add_plugins(MinimalPlugins)
+            .add_plugin(InputPlugin)
+            .insert_resource(SimulationClock::Lockstep)
+            .add_plugin(SimulationPlugin)
+            .add_plugins(GameplayPlugins)
+            .add_state(AppState::InGame)
+            .add_startup_system(crate::setup_physics.system());
+    }
+}
+
+fn count_ticks(
+    mut run: ResMut<HeadlessRun>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    rng: Res<GameRng>,
+    clouds: Query<&InPlay, With<Cloud>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    run.ticks += 1;
+    if run.ticks >= run.max_ticks {
+        report(
+            &mut run,
+            &score,
+            &stats,
+            &rng,
+            clouds.iter().filter(|in_play| in_play.0).count(),
+        );
+        exit.send(AppExit);
+    }
+}
+
+fn game_over(
+    mut run: ResMut<HeadlessRun>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    rng: Res<GameRng>,
+    clouds: Query<&InPlay, With<Cloud>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    report(
+        &mut run,
+        &score,
+        &stats,
+        &rng,
+        clouds.iter().filter(|in_play| in_play.0).count(),
+    );
+    exit.send(AppExit);
+}
+
+fn report(
+    run: &mut HeadlessRun,
+    score: &Score,
+    stats: &RunStats,
+    rng: &GameRng,
+    live_clouds: usize,
+) {
+    if run.reported {
+        return;
+    }
+    run.reported = true;
+    let outcome = match stats.death_cause {
+        None => "alive",
+        Some(DeathCause::Earth) => "fell on the earth",
+        Some(DeathCause::EvilCloud) => "hit an evil cloud",
+    };
+    println!("seed: {}", rng.seed());
+    println!("ticks: {} ({:.2}s)", run.ticks, stats.time);
+    println!("outcome: {}", outcome);
+    println!("score: {}", score.get());
+    println!("laps: {}", score.laps());
+    println!("clouds bounced: {}", stats.clouds_bounced);
+    println!("max speed: {:.2}", stats.max_speed);
+    println!("live clouds: {}", live_clouds);
+}
@@ -0,0 +1,252 @@
+use crate::despawn_with;
+use crate::AppState;
+use crate::GameRng;
+use crate::ReplaySession;
+use crate::RunStats;
+use crate::Score;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_HIGH_SCORES: usize = 10;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// seconds since the unix epoch
+    pub date: u64,
+    pub seed: u64,
+    /// seconds survived
+    pub duration: f32,
+}
+
+/// Best runs, sorted by decreasing score, saved in the user data directory
+#[derive(Debug)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+    /// rank of the run that just ended, if it made it to the table
+    pub last_rank: Option<usize>,
+    path: Option<PathBuf>,
+}
+struct HighScoreUI;
+pub struct HighScorePlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct HighScoreUpdate;
+
+impl FromWorld for HighScores {
+    fn from_world(_world: &mut World) -> Self {
+        let path = dirs::data_dir().map(|dir| dir.join("montociel").join("highscores.ron"));
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| match ron::de::from_str(&content) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    error!("could not read high scores: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        HighScores {
+            entries,
+            last_rank: None,
+            path,
+        }
+    }
+}
+
+impl HighScores {
+    pub fn is_new_best(&self) -> bool {
+        self.last_rank == Some(0)
+    }
+
+    /// Insert a run in the table, returns its rank if it is good enough to be kept
+    pub fn insert(&mut self, high_score: HighScore) -> Option<usize> {
+        if high_score.score == 0 {
+            return None;
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| entry.score < high_score.score)
+            .unwrap_or(self.entries.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.entries.insert(rank, high_score);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let content = match ron::ser::to_string_pretty(&self.entries, Default::default()) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("could not serialize high scores: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, content))
+        {
+            error!("could not save high scores to {}: {}", path.display(), e);
+        }
+    }
+}
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HighScores>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Menu).with_system(setup_high_score_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(update_high_scores.system().label(HighScoreUpdate))
+                    .with_system(setup_high_score_ui.system().after(HighScoreUpdate)),
+            )
+            .add_system_set(
+                SystemSet::on_resume(AppState::Menu).with_system(setup_high_score_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Menu)
+                    .with_system(despawn_with::<HighScoreUI>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_pause(AppState::Menu)
+                    .with_system(despawn_with::<HighScoreUI>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::GameOver)
+                    .with_system(despawn_with::<HighScoreUI>.system()),
+            );
+    }
+}
+
+fn player_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "sheep".to_string())
+}
+
+fn update_high_scores(
+    mut high_scores: ResMut<HighScores>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    rng: Res<GameRng>,
+    replay: Res<ReplaySession>,
+) {
+    // a replay is someone's run played again, maybe not even ours
+    if replay.is_playback() {
+        high_scores.last_rank = None;
+        return;
+    }
+    let date = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |date| date.as_secs());
+    let rank = high_scores.insert(HighScore {
+        name: player_name(),
+        score: score.get(),
+        date,
+        seed: rng.seed(),
+        duration: stats.time,
+    });
+    high_scores.last_rank = rank;
+    if rank.is_some() {
+        high_scores.save();
+    }
+}
+
+/// Format a unix timestamp as year-month-day
+fn format_date(timestamp: u64) -> String {
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = timestamp as i64 / 86400 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+fn setup_high_score_ui(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    asset_server: Res<AssetServer>,
+    state: Res<State<AppState>>,
+) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+    let mut sections = vec![TextSection {
+        value: "High scores\n".to_string(),
+        style: TextStyle {
+            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font_size: 30.0,
+            color: Color::WHITE,
+        },
+    }];
+    if high_scores.entries.is_empty() {
+        sections.push(TextSection {
+            value: "no run yet".to_string(),
+            style: style.clone(),
+        });
+    }
+    let is_game_over = *state.current() == AppState::GameOver;
+    for (rank, entry) in high_scores.entries.iter().enumerate() {
+        let color = if is_game_over && high_scores.last_rank == Some(rank) {
+            Color::GOLD
+        } else {
+            Color::WHITE
+        };
+        sections.push(TextSection {
+            value: format!(
+                "{:>2}. {:<12} {:>5} {:>6.1}s {} #{}\n",
+                rank + 1,
+                entry.name,
+                entry.score,
+                entry.duration,
+                format_date(entry.date),
+                entry.seed
+            ),
+            style: TextStyle {
+                color,
+                ..style.clone()
+            },
+        });
+    }
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // ui y axis points up, this is the top left corner
+                position: Rect {
+                    bottom: Val::Px(20.0),
+                    left: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(HighScoreUI);
+}
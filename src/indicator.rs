@@ -0,0 +1,128 @@
+use crate::Earth;
+use crate::Evil;
+use crate::InPlay;
+use crate::MainCamera;
+use crate::Montociel;
+use crate::RunEntity;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier2d::prelude::*;
+use std::f32::consts::PI;
+
+/// space between the indicators and the edge of the screen, in pixels
+const EDGE_MARGIN: f32 = 30.;
+/// only the nearest evil clouds get an indicator
+const MAX_THREAT_INDICATORS: usize = 5;
+/// arrows pointing right then turning counterclockwise
+const ARROWS: [char; 8] = ['→', '↗', '↑', '↖', '←', '↙', '↓', '↘'];
+
+/// Arrow on the edge of the screen pointing at an entity out of view, with its distance
+struct OffScreenIndicator {
+    target: Entity,
+}
+
+/// Point at the sheep and the nearest evil clouds when they are off screen
+pub struct IndicatorPlugin;
+
+impl Plugin for IndicatorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(update_indicators.system());
+    }
+}
+
+fn update_indicators(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    windows: Res<Windows>,
+    rapier_config: Res<RapierConfiguration>,
+    cameras: Query<&Transform, With<MainCamera>>,
+    montociel: Query<(Entity, &Transform), With<Montociel>>,
+    evil_clouds: Query<(Entity, &Transform, &InPlay), (With<Evil>, Without<Earth>)>,
+    mut indicators: Query<(Entity, &OffScreenIndicator, &mut Style, &mut Text, &Node)>,
+) {
+    let (window, camera) = match (windows.get_primary(), cameras.iter().next()) {
+        (Some(window), Some(camera)) => (window, camera),
+        _ => return,
+    };
+    let half_size = Vec2::new(window.width(), window.height()) / 2.;
+    let center = camera.translation.truncate();
+    let zoom = camera.scale.x;
+
+    // screen position of each target relative to the center of the screen, in pixels
+    let mut targets = Vec::new();
+    let sheep = montociel.iter().next();
+    if let Some((entity, transform)) = sheep {
+        targets.push((entity, transform.translation.truncate(), Color::WHITE));
+    }
+    let near = sheep.map_or(center, |(_, transform)| transform.translation.truncate());
+    let mut threats: Vec<(Entity, Vec2)> = evil_clouds
+        .iter()
+        .filter(|(_, _, in_play)| in_play.0)
+        .map(|(entity, transform, _)| (entity, transform.translation.truncate()))
+        .collect();
+    threats.sort_by(|(_, a), (_, b)| {
+        a.distance_squared(near)
+            .total_cmp(&b.distance_squared(near))
+    });
+    threats.truncate(MAX_THREAT_INDICATORS);
+    targets.extend(
+        threats
+            .into_iter()
+            .map(|(entity, position)| (entity, position, Color::rgb(0.9, 0.2, 0.2))),
+    );
+    let mut off_screen: HashMap<Entity, (Vec2, Color)> = targets
+        .into_iter()
+        .filter_map(|(entity, position, color)| {
+            let relative = (position - center) / zoom;
+            let outside = relative.x.abs() > half_size.x || relative.y.abs() > half_size.y;
+            if outside {
+                Some((entity, (relative, color)))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for (entity, indicator, mut style, mut text, node) in indicators.iter_mut() {
+        let (relative, _) = match off_screen.remove(&indicator.target) {
+            Some(target) => target,
+            None => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        };
+        // the point of the edge of the screen on the way to the target
+        let inner = half_size - Vec2::splat(EDGE_MARGIN);
+        let edge = relative * f32::min(inner.x / relative.x.abs(), inner.y / relative.y.abs());
+        // ui y axis points up, from the bottom left corner
+        let corner = half_size + edge - node.size / 2.;
+        style.position.left = Val::Px(corner.x);
+        style.position.bottom = Val::Px(corner.y);
+        let angle = f32::atan2(relative.y, relative.x);
+        let arrow = ARROWS[(angle / (PI / 4.)).round().rem_euclid(8.) as usize];
+        let distance = relative.length() * zoom / rapier_config.scale;
+        text.sections[0].value = format!("{} {:.0}", arrow, distance);
+    }
+
+    for (target, (_, color)) in off_screen {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 25.0,
+                        color,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(OffScreenIndicator { target })
+            .insert(RunEntity);
+    }
+}
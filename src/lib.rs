@@ -0,0 +1,261 @@
+// bevy systems take their resources and queries as arguments
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use bevy::app::PluginGroupBuilder;
+#[cfg(target_arch = "wasm32")]
+use bevy::asset::AssetServerSettings;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier2d::physics::TimestepMode;
+use bevy_rapier2d::prelude::*;
+use std::path::PathBuf;
+
+mod camera;
+mod cloud;
+mod controls;
+mod dash;
+mod debug;
+mod difficulty;
+mod gravity;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+mod highscore;
+mod indicator;
+mod lives;
+mod montociel;
+mod pattern;
+mod pause;
+mod powerup;
+mod replay;
+mod rng;
+mod run;
+mod score;
+mod settings;
+mod simulation;
+mod tuning;
+mod ui;
+
+pub use camera::*;
+pub use cloud::*;
+pub use controls::*;
+pub use dash::*;
+pub use debug::*;
+pub use difficulty::*;
+pub use gravity::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use headless::*;
+pub use highscore::*;
+pub use indicator::*;
+pub use lives::*;
+pub use montociel::*;
+pub use pattern::*;
+pub use pause::*;
+pub use powerup::*;
+pub use replay::*;
+pub use rng::*;
+pub use run::*;
+pub use score::*;
+pub use settings::*;
+pub use simulation::*;
+pub use tuning::*;
+pub use ui::*;
+
+struct Materials {
+    //TODO background image or smthing
+    montociel_material: Handle<ColorMaterial>,
+    cloud_material: Handle<ColorMaterial>,
+    evil_cloud_material: Handle<ColorMaterial>,
+    earth_material: Handle<ColorMaterial>,
+    moon_material: Handle<ColorMaterial>,
+    power_up_materials: HashMap<CloudKind, Handle<ColorMaterial>>,
+}
+
+impl FromWorld for Materials {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        let montociel_asset = asset_server.load("montociel.png");
+        let cloud_asset = asset_server.load("cloud.png");
+        let earth_asset = asset_server.load("earth.png");
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        let montociel_material = materials.add(montociel_asset.into());
+        let cloud_material = materials.add(cloud_asset.clone().into());
+        let evil_cloud_material = materials.add(ColorMaterial::modulated_texture(
+            cloud_asset.clone(),
+            Color::rgb(0.35, 0.3, 0.4),
+        ));
+        let power_up_materials = CloudKind::POWER_UPS
+            .iter()
+            .map(|kind| {
+                let material = ColorMaterial::modulated_texture(cloud_asset.clone(), kind.color());
+                (*kind, materials.add(material))
+            })
+            .collect();
+        let earth_material = materials.add(earth_asset.clone().into());
+        let moon_material = materials.add(ColorMaterial::modulated_texture(
+            earth_asset,
+            Color::rgb(0.7, 0.7, 0.75),
+        ));
+        Materials {
+            montociel_material,
+            cloud_material,
+            evil_cloud_material,
+            earth_material,
+            moon_material,
+            power_up_materials,
+        }
+    }
+}
+
+impl Materials {
+    fn cloud(&self, is_evil: bool, kind: CloudKind) -> &Handle<ColorMaterial> {
+        if is_evil {
+            return &self.evil_cloud_material;
+        }
+        self.power_up_materials
+            .get(&kind)
+            .unwrap_or(&self.cloud_material)
+    }
+}
+
+/// Command line arguments
+#[derive(Debug, Default)]
+pub struct Args {
+    /// seed of the cloud field, `--seed <n>`
+    seed: Option<u64>,
+    /// record the inputs of each run to this file, `--record <file>`
+    record: Option<PathBuf>,
+    /// play the inputs recorded in this file, `--replay <file>`
+    replay: Option<PathBuf>,
+    /// simulate this many ticks without window then print the outcome, `--headless <ticks>`
+    headless: Option<u32>,
+    /// inputs of the headless run, `--script <actions:ticks,...>`
+    script: Option<String>,
+    /// layout of the gravity wells, `--level <name>`
+    level: Option<String>,
+}
+
+impl Args {
+    fn from_env() -> Self {
+        let mut args = Args::default();
+        let mut env_args = std::env::args().skip(1);
+        while let Some(arg) = env_args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    args.seed = env_args.next().and_then(|seed| seed.parse().ok());
+                    if args.seed.is_none() {
+                        eprintln!("--seed expects an unsigned integer");
+                    }
+                }
+                "--record" => args.record = env_args.next().map(PathBuf::from),
+                "--replay" => args.replay = env_args.next().map(PathBuf::from),
+                "--headless" => {
+                    args.headless = env_args.next().and_then(|ticks| ticks.parse().ok());
+                    if args.headless.is_none() {
+                        eprintln!("--headless expects a number of ticks");
+                    }
+                }
+                "--script" => args.script = env_args.next(),
+                "--level" => args.level = env_args.next(),
+                _ => eprintln!("unknown argument {}", arg),
+            }
+        }
+        args
+    }
+}
+
+fn setup_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.scale = 15.;
+    rapier_config.gravity = Vec2::new(0.0, 0.0).into();
+    // one physics step of `IntegrationParameters::dt` per frame, so runs can be replayed tick by tick
+    rapier_config.timestep_mode = TimestepMode::FixedTimestep;
+}
+
+fn setup(mut commands: Commands) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(MainCamera);
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum AppState {
+    Menu,
+    InGame,
+    /// pushed on top of `InGame`, the run stays on screen
+    GameOver,
+    /// pushed on top of `InGame`
+    Paused,
+    /// pushed on top of `Menu` or `Paused`
+    Settings,
+    /// pushed on top of `Settings`
+    Controls,
+}
+
+/// Plugins of the game itself, they run without window nor rendering
+pub struct GameplayPlugins;
+
+impl PluginGroup for GameplayPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(MontocielPlugin)
+            .add(GravityPlugin)
+            .add(CloudPlugin)
+            .add(CloudPatternPlugin)
+            .add(DifficultyPlugin)
+            .add(RngPlugin)
+            .add(ControlsPlugin)
+            .add(ReplayPlugin)
+            .add(RunPlugin)
+            .add(ScorePlugin)
+            .add(PowerUpPlugin)
+            .add(LivesPlugin)
+            .add(DashPlugin);
+    }
+}
+
+/// Run the game, or a headless simulation when asked on the command line
+pub fn run() {
+    let args = Args::from_env();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.headless.is_some() {
+        return run_headless(args);
+    }
+
+    let mut app = App::build();
+
+    app.insert_resource(args);
+
+    app.add_plugins(DefaultPlugins);
+
+    // when building for Web, use WebGL2 rendering
+    #[cfg(target_arch = "wasm32")]
+    app.add_plugin(bevy_webgl2::WebGL2Plugin);
+
+    // the web build serves the assets from the root of the site
+    #[cfg(target_arch = "wasm32")]
+    app.insert_resource(AssetServerSettings {
+        asset_folder: "/".to_string(),
+    });
+
+    app.add_plugin(SimulationPlugin)
+        .insert_resource(ClearColor(Color::rgb(1.0, 0.714, 0.757)))
+        .init_resource::<Materials>()
+        .add_plugin(TuningPlugin)
+        // before the gameplay, the saved bindings replace the default ones
+        .add_plugin(SettingsPlugin)
+        .add_plugins(GameplayPlugins)
+        .add_plugin(ScoreUIPlugin)
+        .add_plugin(PowerUpUIPlugin)
+        .add_plugin(HighScorePlugin)
+        .add_plugin(UIPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(IndicatorPlugin)
+        .add_plugin(DebugPlugin)
+        .add_state(AppState::Menu)
+        .add_startup_system(setup_physics.system())
+        .add_startup_system(setup.system());
+
+    app.run();
+}
@@ -0,0 +1,69 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::CloudCollision;
+use crate::Montociel;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
+use bevy_rapier2d::prelude::*;
+use std::time::Duration;
+
+/// seconds the sheep stays hidden, then shown, while it blinks
+const BLINK_PERIOD: f32 = 0.1;
+
+/// Hits the sheep can still take in this run, the run ends when the last one is lost
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lives(pub u32);
+
+/// The sheep was just hit, hazards only push it away until the timer ends
+pub struct Invulnerable(pub Timer);
+
+pub struct LivesPlugin;
+
+impl Plugin for LivesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Lives>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(reset_lives.system()))
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame).with_system(
+                    tick_invulnerability
+                        .system()
+                        .after(PhysicsSystems::StepWorld)
+                        .before(CloudCollision),
+                ),
+            )
+            .add_system(blink.system());
+    }
+}
+
+fn reset_lives(mut lives: ResMut<Lives>, tuning: Res<Tuning>) {
+    lives.0 = tuning.lives.max(1);
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    integration_parameters: Res<IntegrationParameters>,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+) {
+    let dt = Duration::from_secs_f32(integration_parameters.dt);
+    for (entity, mut invulnerable) in query.iter_mut() {
+        if invulnerable.0.tick(dt).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+/// Blink the sheep while it is invulnerable
+fn blink(mut query: Query<(&mut Visible, Option<&Invulnerable>), With<Montociel>>) {
+    for (mut visible, invulnerable) in query.iter_mut() {
+        let is_visible = match invulnerable {
+            Some(invulnerable) => (invulnerable.0.elapsed_secs() / BLINK_PERIOD) as u32 % 2 == 1,
+            None => true,
+        };
+        if visible.is_visible != is_visible {
+            visible.is_visible = is_visible;
+        }
+    }
+}
@@ -1,25 +1,73 @@
+use crate::main_well;
+use crate::on_tick;
+use crate::Action;
 use crate::AppState;
+use crate::Cloud;
+use crate::CloudKind;
+use crate::CloudPool;
+use crate::Dash;
+use crate::DeathCause;
+use crate::Earth;
 use crate::Evil;
+use crate::GravityWell;
+use crate::Invulnerable;
+use crate::Lives;
 use crate::Materials;
+use crate::PlayerInput;
+use crate::PlayerInputLabel;
+use crate::PowerUps;
+use crate::RunEntity;
+use crate::RunStats;
 use crate::Score;
+use crate::SimulationStage;
+use crate::Tuning;
 use bevy::prelude::*;
+use bevy_rapier2d::physics::PhysicsSystems;
 use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::geometry::NarrowPhase;
+
+/// how far the held thrust tilts a bounce along the tangent, 1 is 45 degrees
+const JUMP_AIM: f32 = 1.;
 
 pub struct Montociel;
 pub struct MontocielPlugin;
 
+/// Reacting to the contacts of the last physics step
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CloudCollision;
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct Steering;
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+struct Drag;
+
 impl Plugin for MontocielPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system_set(
             SystemSet::on_enter(AppState::InGame).with_system(spawn_montociel.system()),
         )
-        .add_system_set(
-            SystemSet::on_update(AppState::InGame)
-                .with_system(input_movement.system())
-                .with_system(montociel_aerodynamism.system())
-                .with_system(update_forces.system())
-                .with_system(clamp_velocity.system())
-                .with_system(cloud_collision.system()),
+        .add_system_set_to_stage(
+            SimulationStage,
+            on_tick(AppState::InGame)
+                // a fixed order around the physics step keeps runs replayable
+                .with_system(
+                    input_movement
+                        .system()
+                        .label(Steering)
+                        .after(PlayerInputLabel),
+                )
+                .with_system(montociel_aerodynamism.system().label(Drag).after(Steering))
+                .with_system(
+                    clamp_velocity
+                        .system()
+                        .after(Drag)
+                        .before(PhysicsSystems::StepWorld),
+                )
+                .with_system(
+                    cloud_collision
+                        .system()
+                        .label(CloudCollision)
+                        .after(PhysicsSystems::StepWorld),
+                ),
         );
     }
 }
@@ -27,10 +75,11 @@ impl Plugin for MontocielPlugin {
 fn spawn_montociel(
     mut commands: Commands,
     rapier_config: ResMut<RapierConfiguration>,
-    materials: Res<Materials>,
+    materials: Option<Res<Materials>>,
+    tuning: Res<Tuning>,
 ) {
     //Spawn Montociel
-    let radius = 30. / rapier_config.scale;
+    let radius = tuning.montociel_radius / rapier_config.scale;
     let rigid_body = RigidBodyBundle {
         body_type: RigidBodyType::Dynamic,
         position: Vec2::new(10., 10.).into(),
@@ -52,96 +101,135 @@ fn spawn_montociel(
         ..Default::default()
     };
 
-    commands
-        .spawn_bundle(rigid_body)
+    let mut montociel = commands.spawn_bundle(rigid_body);
+    montociel
         .insert_bundle(collider)
-        .insert_bundle(SpriteBundle {
+        .insert(RigidBodyPositionSync::Discrete)
+        .insert(Montociel)
+        .insert(Dash::default())
+        .insert(RunEntity);
+    if let Some(materials) = materials {
+        montociel.insert_bundle(SpriteBundle {
             material: materials.montociel_material.clone(),
             sprite: Sprite::new(Vec2::new(
                 2. * radius * rapier_config.scale,
                 2. * radius * rapier_config.scale,
             )),
             ..Default::default()
-        })
-        .insert(RigidBodyPositionSync::Discrete)
-        .insert(Montociel);
+        });
+    }
 }
 
 fn input_movement(
-    mouse_input: Res<Input<MouseButton>>,
+    input: Res<PlayerInput>,
     rapier_parameters: Res<RapierConfiguration>,
-    mut montociel_info: Query<(&Montociel, &mut RigidBodyVelocity, &RigidBodyPosition)>,
+    tuning: Res<Tuning>,
+    wells: Query<&GravityWell>,
+    mut montociel_info: Query<
+        (&mut Dash, &mut RigidBodyVelocity, &RigidBodyPosition),
+        With<Montociel>,
+    >,
 ) {
-    for (_, mut velocity, pos) in montociel_info.iter_mut() {
-        if mouse_input.get_pressed().len() > 0 {
-            let x = pos.position.translation.x;
-            let y = pos.position.translation.y;
-            let theta = std::f32::consts::PI / 2.;
-            let mut move_delta = Vec2::new(
-                -x * f32::cos(theta) + y * f32::sin(theta),
-                -x * f32::sin(theta) - y * f32::cos(theta),
-            );
-
-            if move_delta != Vec2::new(0., 0.) {
-                // Note that the RapierConfiguration::Scale factor is also used here to transform
-                // the move_delta from: 'pixels/second' to 'physics_units/second'
-                move_delta.normalize();
-                move_delta /= rapier_parameters.scale;
-            }
+    for (mut dash, mut velocity, pos) in montociel_info.iter_mut() {
+        let position = around_main_well(&wells, &tuning, pos);
+        let (x, y) = (position.x, position.y);
+        // clockwise tangent to the main well
+        let theta = std::f32::consts::PI / 2.;
+        let mut move_delta = Vec2::new(
+            -x * f32::cos(theta) + y * f32::sin(theta),
+            -x * f32::sin(theta) - y * f32::cos(theta),
+        );
+
+        if move_delta != Vec2::new(0., 0.) {
+            // Note that the RapierConfiguration::Scale factor is also used here to transform
+            // the move_delta from: 'pixels/second' to 'physics_units/second'
+            move_delta.normalize();
+            move_delta /= rapier_parameters.scale;
+        }
+
+        let direction = thrust_direction(&input);
 
-            // Update the velocity on the rigid_body_component,
-            // the bevy_rapier plugin will update the Sprite transform.
-            // cringeee
-            let power = 0.8;
-            let v_x = power * move_delta.x + velocity.linvel.x;
-            let v_y = power * move_delta.y + velocity.linvel.y;
+        // Update the velocity on the rigid_body_component,
+        // the bevy_rapier plugin will update the Sprite transform.
+        // cringeee
+        let power = tuning.thrust_power;
+        let v_x = direction * power * move_delta.x + velocity.linvel.x;
+        let v_y = direction * power * move_delta.y + velocity.linvel.y;
+        velocity.linvel = Vec2::new(v_x, v_y).into();
+
+        if input.pressed(Action::Brake) {
+            velocity.linvel *= tuning.brake;
+        }
+
+        if input.just_pressed(Action::Dash)
+            && dash.is_ready(&tuning)
+            && move_delta != Vec2::new(0., 0.)
+        {
+            dash.start(&tuning);
+            // burst of speed along the tangent, in the direction the sheep is going
+            let tangent = move_delta.normalize();
+            let going_clockwise =
+                tangent.x * velocity.linvel.x + tangent.y * velocity.linvel.y >= 0.;
+            let dash_direction = if going_clockwise { tangent } else { -tangent };
+            let dash_speed = tuning.dash_speed;
+            let v_x = dash_speed * dash_direction.x + velocity.linvel.x;
+            let v_y = dash_speed * dash_direction.y + velocity.linvel.y;
             velocity.linvel = Vec2::new(v_x, v_y).into();
         }
     }
 }
 
-fn montociel_aerodynamism(mut velocities: Query<&mut RigidBodyVelocity, With<Montociel>>) {
-    for mut velocity in velocities.iter_mut() {
-        let v_x = velocity.linvel.x;
-        let v_y = velocity.linvel.y;
-        let velocity_slowed_by_air = 0.95 * Vec2::new(v_x, v_y);
-        velocity.linvel = velocity_slowed_by_air.into();
+/// 1 when thrusting clockwise, -1 counterclockwise, 0 when both or none
+fn thrust_direction(input: &PlayerInput) -> f32 {
+    let mut direction = 0.;
+    if input.pressed(Action::ThrustClockwise) {
+        direction += 1.;
     }
+    if input.pressed(Action::ThrustCounterClockwise) {
+        direction -= 1.;
+    }
+    direction
 }
 
-fn update_forces(
-    mut rigid_bodies: Query<
-        (
-            &mut RigidBodyForces,
-            &RigidBodyPosition,
-            &RigidBodyMassProps,
-        ),
-        With<Montociel>,
-    >,
+/// Air drag over the physics step rather than each frame, thinner away from the main well
+/// when there is an atmosphere
+fn montociel_aerodynamism(
+    tuning: Res<Tuning>,
+    rapier_config: Res<RapierConfiguration>,
+    integration_parameters: Res<IntegrationParameters>,
+    wells: Query<&GravityWell>,
+    mut velocities: Query<(&mut RigidBodyVelocity, &RigidBodyPosition), With<Montociel>>,
 ) {
-    for (mut rb_forces, rb_pos, rb_mass) in rigid_bodies.iter_mut() {
-        let x = rb_pos.position.translation.x;
-        let y = rb_pos.position.translation.y;
-        let d2 = x * x + y * y;
-        let norm = f32::sqrt(d2);
-        let g = 100.;
-        let eps = 0.0001;
-        let nimp = -g * rb_mass.mass() * 1. / (norm + eps);
-        let gravity = Vec2::new(nimp * x, nimp * y).into();
-        rb_forces.force = gravity;
+    for (mut velocity, pos) in velocities.iter_mut() {
+        let mut density = 1.;
+        if tuning.atmosphere_height > 0. {
+            let position = pos.position.translation.vector.into();
+            if let Some(well) = main_well(wells.iter(), &tuning, position) {
+                let altitude =
+                    (position.distance(well.position) - well.radius) * rapier_config.scale;
+                density = f32::exp(-altitude.max(0.) / tuning.atmosphere_height);
+            }
+        }
+        let kept = f32::exp(-tuning.air_drag * density * integration_parameters.dt);
+        let v_x = velocity.linvel.x;
+        let v_y = velocity.linvel.y;
+        let velocity_slowed_by_air = kept * Vec2::new(v_x, v_y);
+        velocity.linvel = velocity_slowed_by_air.into();
     }
 }
 
+/// Cap the speed, higher for a moment after a dash
 fn clamp_velocity(
+    tuning: Res<Tuning>,
     mut bodies_info: Query<
-        &mut RigidBodyVelocity, //, With<Montociel>
+        (&mut RigidBodyVelocity, Option<&Dash>), //, With<Montociel>
     >,
 ) {
-    for mut velocity in bodies_info.iter_mut() {
+    for (mut velocity, dash) in bodies_info.iter_mut() {
         let v_x = velocity.linvel.x;
         let v_y = velocity.linvel.y;
         let magnitude = f32::sqrt(v_x * v_x + v_y * v_y);
-        let max_magnitude = 70.;
+        let max_magnitude = dash.map_or(tuning.max_speed, |dash| dash.max_speed(&tuning));
         if magnitude > 0.01 {
             let clamped_velocity =
                 Vec2::new(v_x, v_y) / magnitude * f32::min(magnitude, max_magnitude);
@@ -152,18 +240,41 @@ fn clamp_velocity(
 
 fn cloud_collision(
     mut commands: Commands,
+    mut pool: ResMut<CloudPool>,
     mut score: ResMut<Score>,
+    mut stats: ResMut<RunStats>,
+    mut power_ups: ResMut<PowerUps>,
+    mut lives: ResMut<Lives>,
     mut state: ResMut<State<AppState>>,
     mut montociel_info: Query<
-        (Entity, &mut RigidBodyVelocity, &RigidBodyPosition),
+        (
+            Entity,
+            &mut RigidBodyVelocity,
+            &RigidBodyPosition,
+            Option<&Invulnerable>,
+            &mut Dash,
+        ),
         With<Montociel>,
     >,
     mut contact_events: EventReader<ContactEvent>,
-    query: Query<Entity, With<Evil>>,
+    hazards: Query<(Option<&RigidBodyPosition>, Option<&Earth>), With<Evil>>,
+    clouds: Query<Option<&CloudKind>, With<Cloud>>,
+    wells: Query<&GravityWell>,
+    narrow_phase: Res<NarrowPhase>,
+    input: Res<PlayerInput>,
     rapier_config: Res<RapierConfiguration>,
+    tuning: Res<Tuning>,
 ) {
+    let aim = thrust_direction(&input);
+    // the invulnerability of a hit starts with the commands, after this tick
+    let mut hit = false;
+    // the run ended on an earlier contact of this tick, the others are only read
+    let mut dead = false;
     for contact_event in contact_events.iter() {
-        for (montociel_entity, mut vel, pos) in montociel_info.iter_mut() {
+        if dead {
+            continue;
+        }
+        for (montociel_entity, mut vel, pos, invulnerable, mut dash) in montociel_info.iter_mut() {
             match contact_event {
                 ContactEvent::Started(collider1, collider2) => {
                     let entity1 = collider1.entity();
@@ -176,13 +287,67 @@ fn cloud_collision(
                     } else {
                         entity1
                     };
-                    if query.get(entity).is_ok() {
-                        state.set(AppState::GameOver).unwrap();
-                    } else {
-                        commands.entity(entity).despawn();
-                        jump(pos, &mut vel, &rapier_config);
+                    let normal =
+                        contact_normal(&narrow_phase, *collider1, *collider2, montociel_entity);
+                    if let Ok((hazard, earth)) = hazards.get(entity) {
+                        if invulnerable.is_some() || hit {
+                            knockback(hazard, pos, &mut vel, &tuning);
+                            continue;
+                        }
+                        if power_ups.consume(CloudKind::Shield) {
+                            // the shield takes the hit, the sheep bounces off
+                            if earth.is_none() {
+                                pool.park(&mut commands, entity, true);
+                            }
+                            jump(
+                                &wells,
+                                &tuning,
+                                pos,
+                                &mut vel,
+                                &rapier_config,
+                                tuning.jump_power,
+                                normal,
+                                aim,
+                            );
+                            score.break_combo();
+                            continue;
+                        }
+                        lives.0 = lives.0.saturating_sub(1);
+                        if lives.0 > 0 {
+                            hit = true;
+                            knockback(hazard, pos, &mut vel, &tuning);
+                            commands.entity(montociel_entity).insert(Invulnerable(
+                                Timer::from_seconds(tuning.invulnerability, false),
+                            ));
+                            score.break_combo();
+                            continue;
+                        }
+                        stats.death_cause = Some(if earth.is_some() {
+                            DeathCause::Earth
+                        } else {
+                            DeathCause::EvilCloud
+                        });
+                        state.push(AppState::GameOver).unwrap();
+                        dead = true;
+                    } else if let Ok(kind) = clouds.get(entity) {
+                        pool.park(&mut commands, entity, false);
+                        power_ups.activate(kind.copied().unwrap_or(CloudKind::Plain));
+                        jump(
+                            &wells,
+                            &tuning,
+                            pos,
+                            &mut vel,
+                            &rapier_config,
+                            tuning.jump_power * power_ups.jump_factor(),
+                            normal,
+                            aim,
+                        );
                         //increment score
-                        score.incr();
+                        score.incr(power_ups.score_bonus());
+                        dash.refill(&tuning);
+                        stats.clouds_bounced += 1;
+                    } else {
+                        score.break_combo();
                     }
                 }
                 ContactEvent::Stopped(_collider1, _collider2) => {}
@@ -191,24 +356,69 @@ fn cloud_collision(
     }
 }
 
+/// Throw the sheep away from a hazard it hit, away from the origin when the hazard has no position
+fn knockback(
+    hazard: Option<&RigidBodyPosition>,
+    pos: &RigidBodyPosition,
+    vel: &mut RigidBodyVelocity,
+    tuning: &Tuning,
+) {
+    let position: Vec2 = pos.position.translation.vector.into();
+    let hazard = hazard.map_or(Vec2::ZERO, |hazard| {
+        hazard.position.translation.vector.into()
+    });
+    let away = (position - hazard).normalize_or_zero();
+    vel.linvel = (away * tuning.knockback).into();
+}
+
+/// Position of the sheep relative to the well it turns around
+fn around_main_well(wells: &Query<&GravityWell>, tuning: &Tuning, pos: &RigidBodyPosition) -> Vec2 {
+    let position = pos.position.translation.vector.into();
+    match main_well(wells.iter(), tuning, position) {
+        Some(well) => position - well.position,
+        None => position,
+    }
+}
+
+/// Normal of the contact pushing the sheep away from what it touched, if rapier has one
+fn contact_normal(
+    narrow_phase: &NarrowPhase,
+    collider1: ColliderHandle,
+    collider2: ColliderHandle,
+    montociel: Entity,
+) -> Option<Vec2> {
+    let pair = narrow_phase.contact_pair(collider1, collider2)?;
+    let manifold = pair
+        .manifolds
+        .iter()
+        .find(|manifold| !manifold.points.is_empty())?;
+    let normal: Vec2 = manifold.data.normal.into();
+    // the normal points from the first collider of the pair to the second
+    if pair.collider1.entity() == montociel {
+        Some(-normal)
+    } else {
+        Some(normal)
+    }
+}
+
+/// Bounce along the contact normal, tilted along the tangent of the main well by `aim`,
+/// the thrust held. Straight away from the main well when the contact has no normal.
 fn jump(
+    wells: &Query<&GravityWell>,
+    tuning: &Tuning,
     pos: &RigidBodyPosition,
     vel: &mut RigidBodyVelocity,
     rapier_config: &Res<RapierConfiguration>,
+    power: f32,
+    normal: Option<Vec2>,
+    aim: f32,
 ) {
-    let x = pos.position.translation.x;
-    let y = pos.position.translation.y;
-    let theta = 3. * std::f32::consts::PI / 4.;
-    let mut move_delta = Vec2::new(
-        -x * f32::cos(theta) + y * f32::sin(theta),
-        -x * f32::sin(theta) - y * f32::cos(theta),
-    );
-    if move_delta != Vec2::new(0., 0.) {
-        move_delta.normalize();
-        move_delta /= rapier_config.scale;
-    }
-    let power = 70.;
-    let v_x = power * move_delta.x + vel.linvel.x;
-    let v_y = power * move_delta.y + vel.linvel.y;
-    vel.linvel = Vec2::new(v_x, v_y).into();
+    let position = around_main_well(wells, tuning, pos);
+    let normal = normal.unwrap_or_else(|| position.normalize_or_zero());
+    // clockwise tangent to the main well
+    let tangent = Vec2::new(position.y, -position.x).normalize_or_zero();
+    let direction = (normal + aim * JUMP_AIM * tangent).normalize_or_zero();
+    // bounces get stronger away from the main well
+    let strength = power * position.length() / rapier_config.scale;
+    vel.linvel = (Vec2::from(vel.linvel) + direction * strength).into();
 }
@@ -0,0 +1,258 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::Cloud;
+use crate::CloudMotion;
+use crate::GameRng;
+use crate::InPlay;
+use crate::Montociel;
+use crate::SimulationStage;
+use crate::Tuning;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+/// seconds a cloud orbits before flying away along its tangent
+const ORBIT_TIME: f32 = 20.;
+/// how far a drifting cloud turns away from its heading, in radians
+const DRIFT_AMPLITUDE: f32 = 0.6;
+/// drifts left and right per second
+const DRIFT_FREQUENCY: f32 = 0.3;
+/// part of the speed of a spiralling cloud going toward the center
+const SPIRAL_INWARD: f32 = 0.5;
+/// where spiralling clouds start, relative to `Tuning::play_area_radius`
+const SPIRAL_START: f32 = 0.3;
+/// in radians per second
+const HOMING_TURN_RATE: f32 = 0.5;
+/// seconds an evil cloud follows the sheep before giving up
+const HOMING_TIME: f32 = 8.;
+
+/// How a cloud moves, at the speed it was spawned with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloudPattern {
+    /// straight ahead
+    Straight,
+    /// circles around `center`, in physics units, then flies away
+    Orbit {
+        center: Vec2,
+        clockwise: bool,
+        time_left: f32,
+    },
+    /// weaves left and right of its heading
+    Drift { time: f32 },
+    /// circles around `center` while closing in, until `until` from it
+    Spiral {
+        center: Vec2,
+        clockwise: bool,
+        until: f32,
+    },
+    /// evil clouds only, slowly turns toward the sheep then gives up
+    Homing { time_left: f32 },
+}
+
+/// Odds of each motion pattern for the spawned clouds
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PatternWeights {
+    pub straight: f32,
+    pub orbit: f32,
+    pub drift: f32,
+    pub spiral: f32,
+    /// only for evil clouds
+    pub homing: f32,
+}
+
+/// Steering the clouds by their pattern
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CloudSteering;
+
+pub struct CloudPatternPlugin;
+
+impl Default for PatternWeights {
+    fn default() -> Self {
+        PatternWeights {
+            straight: 6.,
+            orbit: 1.,
+            drift: 1.,
+            spiral: 1.,
+            homing: 1.,
+        }
+    }
+}
+
+impl CloudPattern {
+    /// Pick a pattern for a cloud spawned around this center, in physics units
+    pub fn roll(
+        rng: &mut GameRng,
+        tuning: &Tuning,
+        rapier_config: &RapierConfiguration,
+        center: Vec2,
+        size: f32,
+        is_evil: bool,
+    ) -> Self {
+        use rand::distributions::{Distribution, WeightedIndex};
+        use rand::Rng;
+
+        let weights = &tuning.cloud_patterns;
+        let homing = if is_evil { weights.homing } else { 0. };
+        let choices = [
+            weights.straight,
+            weights.orbit,
+            weights.drift,
+            weights.spiral,
+            homing,
+        ]
+        .map(|weight| weight.max(0.));
+        if choices[1..].iter().all(|weight| *weight == 0.) {
+            return CloudPattern::Straight;
+        }
+        let index = match WeightedIndex::new(choices) {
+            Ok(distribution) => distribution.sample(rng),
+            Err(_) => 0,
+        };
+        match index {
+            1 => CloudPattern::Orbit {
+                center,
+                clockwise: rng.gen_bool(0.5),
+                time_left: ORBIT_TIME,
+            },
+            2 => CloudPattern::Drift { time: 0. },
+            3 => CloudPattern::Spiral {
+                center,
+                clockwise: rng.gen_bool(0.5),
+                until: (size * tuning.earth_radius + tuning.cloud_radius) / rapier_config.scale,
+            },
+            4 => CloudPattern::Homing {
+                time_left: HOMING_TIME,
+            },
+            _ => CloudPattern::Straight,
+        }
+    }
+
+    /// Distance from the center the cloud spawns at, in physics units, if the pattern needs its own
+    pub fn spawn_distance(
+        &self,
+        tuning: &Tuning,
+        rapier_config: &RapierConfiguration,
+    ) -> Option<f32> {
+        match self {
+            CloudPattern::Orbit { .. } => Some(tuning.cloud_belt_distance),
+            CloudPattern::Spiral { .. } => {
+                Some(SPIRAL_START * tuning.play_area_radius / rapier_config.scale)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Plugin for CloudPatternPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set_to_stage(
+            SimulationStage,
+            on_tick(AppState::InGame).with_system(
+                steer_clouds
+                    .system()
+                    .label(CloudSteering)
+                    .before(CloudMotion),
+            ),
+        )
+        .add_system_set_to_stage(
+            SimulationStage,
+            on_tick(AppState::GameOver).with_system(
+                steer_clouds
+                    .system()
+                    .label(CloudSteering)
+                    .before(CloudMotion),
+            ),
+        );
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+/// Tangent of the circle around `center` going through `position`
+fn tangent(position: Vec2, center: Vec2, clockwise: bool) -> Vec2 {
+    let radial = (position - center).normalize_or_zero();
+    let clockwise_tangent = Vec2::new(radial.y, -radial.x);
+    if clockwise {
+        clockwise_tangent
+    } else {
+        -clockwise_tangent
+    }
+}
+
+/// Turn the velocity of each cloud as its pattern says, keeping its speed
+fn steer_clouds(
+    integration_parameters: Res<IntegrationParameters>,
+    montociel: Query<&RigidBodyPosition, With<Montociel>>,
+    mut clouds: Query<(&mut Cloud, &mut CloudPattern, &RigidBodyPosition, &InPlay)>,
+) {
+    let dt = integration_parameters.dt;
+    let sheep = montociel
+        .iter()
+        .next()
+        .map(|pos| Vec2::from(pos.position.translation.vector));
+    for (mut cloud, mut pattern, pos, in_play) in clouds.iter_mut() {
+        if !in_play.0 {
+            continue;
+        }
+        let position: Vec2 = pos.position.translation.vector.into();
+        let speed = cloud.0.length();
+        match &mut *pattern {
+            CloudPattern::Straight => {}
+            CloudPattern::Orbit {
+                center,
+                clockwise,
+                time_left,
+            } => {
+                cloud.0 = tangent(position, *center, *clockwise) * speed;
+                *time_left -= dt;
+                if *time_left <= 0. {
+                    *pattern = CloudPattern::Straight;
+                }
+            }
+            CloudPattern::Drift { time } => {
+                // the heading swings like a sine, turn by its change this tick
+                let omega = 2. * PI * DRIFT_FREQUENCY;
+                let turn =
+                    DRIFT_AMPLITUDE * (f32::sin(omega * (*time + dt)) - f32::sin(omega * *time));
+                cloud.0 = rotate(cloud.0, turn);
+                *time += dt;
+            }
+            CloudPattern::Spiral {
+                center,
+                clockwise,
+                until,
+            } => {
+                let inward = (*center - position).normalize_or_zero();
+                let around = tangent(position, *center, *clockwise);
+                if position.distance(*center) > *until {
+                    let heading = around * (1. - SPIRAL_INWARD) + inward * SPIRAL_INWARD;
+                    cloud.0 = heading.normalize_or_zero() * speed;
+                } else {
+                    // close to the surface, fly away along the tangent
+                    cloud.0 = around * speed;
+                    *pattern = CloudPattern::Straight;
+                }
+            }
+            CloudPattern::Homing { time_left } => {
+                if let Some(sheep) = sheep {
+                    let wanted = sheep - position;
+                    let angle = f32::atan2(
+                        cloud.0.x * wanted.y - cloud.0.y * wanted.x,
+                        cloud.0.dot(wanted),
+                    );
+                    let max_turn = HOMING_TURN_RATE * dt;
+                    cloud.0 = rotate(cloud.0, angle.clamp(-max_turn, max_turn));
+                }
+                *time_left -= dt;
+                if *time_left <= 0. {
+                    *pattern = CloudPattern::Straight;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::AppState;
+use crate::Settings;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+use bevy_rapier2d::prelude::*;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(pause_on_key.system())
+                .with_system(pause_on_focus_loss.system()),
+        )
+        .add_system_set(SystemSet::on_update(AppState::Paused).with_system(back_on_key.system()))
+        .add_system_set(SystemSet::on_update(AppState::Settings).with_system(back_on_key.system()))
+        .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(freeze_physics.system()))
+        .add_system_set(
+            SystemSet::on_exit(AppState::Paused).with_system(unfreeze_physics.system()),
+        );
+    }
+}
+
+/// Consume a press of a pause key, so the state entered in the same frame doesn't see it
+fn take_pause_key(keys: &mut Input<KeyCode>) -> bool {
+    let pressed = keys.just_pressed(KeyCode::Escape) || keys.just_pressed(KeyCode::P);
+    if pressed {
+        keys.reset(KeyCode::Escape);
+        keys.reset(KeyCode::P);
+    }
+    pressed
+}
+
+fn pause_on_key(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if take_pause_key(&mut keys) {
+        // another transition may already be queued, like a game over
+        let _ = state.push(AppState::Paused);
+    }
+}
+
+fn pause_on_focus_loss(
+    settings: Res<Settings>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for event in focus_events.iter() {
+        if !event.focused && settings.pause_on_focus_loss {
+            let _ = state.push(AppState::Paused);
+        }
+    }
+}
+
+/// Resume from the pause menu, or leave the settings
+fn back_on_key(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if take_pause_key(&mut keys) {
+        let _ = state.pop();
+    }
+}
+
+fn freeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+fn unfreeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+}
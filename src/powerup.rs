@@ -0,0 +1,279 @@
+use crate::on_tick;
+use crate::AppState;
+use crate::Cloud;
+use crate::CloudCollision;
+use crate::CloudMotion;
+use crate::CloudSteering;
+use crate::Evil;
+use crate::GameRng;
+use crate::InPlay;
+use crate::Montociel;
+use crate::RunEntity;
+use crate::SimulationStage;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier2d::physics::PhysicsSystems;
+use bevy_rapier2d::prelude::*;
+use std::time::Duration;
+
+/// distance from the sheep within which the magnet pulls the clouds, in pixels
+const MAGNET_RADIUS: f32 = 300.;
+/// speed the magnet gives the clouds each second, in physics units per second
+const MAGNET_PULL: f32 = 15.;
+const SUPER_JUMP_FACTOR: f32 = 1.5;
+/// speed of the clouds in slow motion
+const SLOW_MOTION_FACTOR: f32 = 0.4;
+
+/// What touching a cloud does on top of the bounce, evil clouds are always plain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CloudKind {
+    Plain,
+    /// survive one evil hit
+    Shield,
+    /// pull the nearby clouds toward the sheep
+    Magnet,
+    /// higher bounces
+    SuperJump,
+    /// slower clouds
+    SlowMotion,
+    /// twice the points for each bounce
+    ScoreDoubler,
+}
+
+/// The power-ups picked up during the run, each with the time it has left
+#[derive(Default)]
+pub struct PowerUps {
+    timers: HashMap<CloudKind, Timer>,
+}
+
+struct PowerUpUI;
+
+pub struct PowerUpPlugin;
+/// The power-ups shown during a run
+pub struct PowerUpUIPlugin;
+
+impl CloudKind {
+    pub const POWER_UPS: [CloudKind; 5] = [
+        CloudKind::Shield,
+        CloudKind::Magnet,
+        CloudKind::SuperJump,
+        CloudKind::SlowMotion,
+        CloudKind::ScoreDoubler,
+    ];
+
+    /// A power-up with this chance, a plain cloud otherwise
+    pub fn roll(rng: &mut GameRng, chance: f32) -> Self {
+        use rand::Rng;
+
+        if chance <= 0. || !rng.gen_bool(chance.min(1.) as f64) {
+            return CloudKind::Plain;
+        }
+        CloudKind::POWER_UPS[rng.gen_range(0..CloudKind::POWER_UPS.len())]
+    }
+
+    /// seconds the effect lasts, the shield also ends with the hit it takes
+    pub fn duration(&self) -> f32 {
+        match self {
+            CloudKind::Plain => 0.,
+            CloudKind::Shield => 15.,
+            CloudKind::Magnet => 8.,
+            CloudKind::SuperJump => 8.,
+            CloudKind::SlowMotion => 5.,
+            CloudKind::ScoreDoubler => 10.,
+        }
+    }
+
+    /// tint of the cloud and of its indicator
+    pub fn color(&self) -> Color {
+        match self {
+            CloudKind::Plain => Color::WHITE,
+            CloudKind::Shield => Color::rgb(0.4, 0.7, 1.),
+            CloudKind::Magnet => Color::rgb(1., 0.45, 0.45),
+            CloudKind::SuperJump => Color::rgb(0.5, 1., 0.5),
+            CloudKind::SlowMotion => Color::rgb(0.75, 0.55, 1.),
+            CloudKind::ScoreDoubler => Color::GOLD,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CloudKind::Plain => "Plain",
+            CloudKind::Shield => "Shield",
+            CloudKind::Magnet => "Magnet",
+            CloudKind::SuperJump => "Super jump",
+            CloudKind::SlowMotion => "Slow motion",
+            CloudKind::ScoreDoubler => "x2",
+        }
+    }
+}
+
+impl PowerUps {
+    /// Start the effect of a cloud, or start it over
+    pub fn activate(&mut self, kind: CloudKind) {
+        if kind != CloudKind::Plain {
+            self.timers
+                .insert(kind, Timer::from_seconds(kind.duration(), false));
+        }
+    }
+
+    pub fn is_active(&self, kind: CloudKind) -> bool {
+        self.timers.contains_key(&kind)
+    }
+
+    /// End an effect early, whether it was active
+    pub fn consume(&mut self, kind: CloudKind) -> bool {
+        self.timers.remove(&kind).is_some()
+    }
+
+    /// seconds left, none when the effect is not active
+    pub fn remaining(&self, kind: CloudKind) -> Option<f32> {
+        self.timers
+            .get(&kind)
+            .map(|timer| timer.duration().as_secs_f32() - timer.elapsed_secs())
+    }
+
+    pub fn jump_factor(&self) -> f32 {
+        if self.is_active(CloudKind::SuperJump) {
+            SUPER_JUMP_FACTOR
+        } else {
+            1.
+        }
+    }
+
+    pub fn cloud_speed_factor(&self) -> f32 {
+        if self.is_active(CloudKind::SlowMotion) {
+            SLOW_MOTION_FACTOR
+        } else {
+            1.
+        }
+    }
+
+    /// points multiplier of a bounce
+    pub fn score_bonus(&self) -> u32 {
+        if self.is_active(CloudKind::ScoreDoubler) {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn tick(&mut self, dt: f32) {
+        let dt = Duration::from_secs_f32(dt);
+        self.timers.retain(|_, timer| !timer.tick(dt).finished());
+    }
+}
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PowerUps>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InGame).with_system(reset_power_ups.system()),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame)
+                    .with_system(
+                        magnet_pull
+                            .system()
+                            .after(CloudSteering)
+                            .before(CloudMotion),
+                    )
+                    // the power-ups picked up this tick get their whole duration
+                    .with_system(
+                        tick_power_ups
+                            .system()
+                            .after(PhysicsSystems::StepWorld)
+                            .before(CloudCollision),
+                    ),
+            );
+    }
+}
+
+impl Plugin for PowerUpUIPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::InGame).with_system(setup_power_up_ui.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame).with_system(update_power_up_ui.system()),
+        );
+    }
+}
+
+fn reset_power_ups(mut power_ups: ResMut<PowerUps>) {
+    power_ups.timers.clear();
+}
+
+fn tick_power_ups(
+    mut power_ups: ResMut<PowerUps>,
+    integration_parameters: Res<IntegrationParameters>,
+) {
+    power_ups.tick(integration_parameters.dt);
+}
+
+/// Speed up the clouds near the sheep toward it
+fn magnet_pull(
+    power_ups: Res<PowerUps>,
+    rapier_config: Res<RapierConfiguration>,
+    integration_parameters: Res<IntegrationParameters>,
+    montociel: Query<&RigidBodyPosition, With<Montociel>>,
+    mut clouds: Query<(&mut Cloud, &RigidBodyPosition, &InPlay), Without<Evil>>,
+) {
+    if !power_ups.is_active(CloudKind::Magnet) {
+        return;
+    }
+    let radius = MAGNET_RADIUS / rapier_config.scale;
+    for sheep in montociel.iter() {
+        let sheep: Vec2 = sheep.position.translation.vector.into();
+        for (mut cloud, pos, in_play) in clouds.iter_mut() {
+            let offset = sheep - Vec2::from(pos.position.translation.vector);
+            if in_play.0 && offset.length() < radius {
+                cloud.0 += offset.normalize_or_zero() * MAGNET_PULL * integration_parameters.dt;
+            }
+        }
+    }
+}
+
+fn setup_power_up_ui(mut commands: Commands) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // ui y axis points up, this is under the score
+                position: Rect {
+                    bottom: Val::Px(70.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(PowerUpUI)
+        .insert(RunEntity);
+}
+
+fn update_power_up_ui(
+    asset_server: Res<AssetServer>,
+    power_ups: Res<PowerUps>,
+    mut query: Query<&mut Text, With<PowerUpUI>>,
+) {
+    if !power_ups.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections = CloudKind::POWER_UPS
+            .iter()
+            .filter_map(|kind| {
+                power_ups.remaining(*kind).map(|remaining| TextSection {
+                    value: format!("{} {:.0}s  ", kind.name(), remaining.ceil()),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 30.0,
+                        color: kind.color(),
+                    },
+                })
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,278 @@
+use crate::on_tick;
+use crate::Action;
+use crate::AppState;
+use crate::Args;
+use crate::Bindings;
+use crate::ConnectedGamepads;
+use crate::GameRng;
+use crate::InputDevices;
+use crate::PlayerInput;
+use crate::PlayerInputLabel;
+use crate::RngReseed;
+use crate::SimulationStage;
+use bevy::input::gamepad::GamepadButton;
+use bevy::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// bumped each time the same inputs and seed give another run, older replays can't be played
+const REPLAY_HEADER: &str = "montociel-replay 2";
+
+/// Inputs of a whole run, the `PlayerInput` bits of each physics tick.
+/// Ticks are run-length encoded since the input rarely changes from one tick to the next.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub seed: u64,
+    /// (input bits, number of ticks)
+    runs: Vec<(u8, u32)>,
+}
+
+/// Position of the next tick to play in a `Replay`
+#[derive(Clone, Copy, Debug, Default)]
+struct ReplayCursor {
+    run: usize,
+    tick: u32,
+}
+
+enum ReplayMode {
+    Live,
+    Record(PathBuf),
+    Playback(ReplayCursor),
+}
+
+pub struct ReplaySession {
+    mode: ReplayMode,
+    replay: Replay,
+    /// the run was saved on game over, leaving it afterwards saves nothing more
+    saved: bool,
+}
+pub struct ReplayPlugin;
+
+impl Replay {
+    pub fn new(seed: u64) -> Self {
+        Replay {
+            seed,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, bits: u8) {
+        match self.runs.last_mut() {
+            Some((last, count)) if *last == bits => *count += 1,
+            _ => self.runs.push((bits, 1)),
+        }
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.runs.iter().map(|(_, count)| count).sum()
+    }
+
+    fn next(&self, cursor: &mut ReplayCursor) -> Option<u8> {
+        let (bits, count) = *self.runs.get(cursor.run)?;
+        cursor.tick += 1;
+        if cursor.tick >= count {
+            cursor.run += 1;
+            cursor.tick = 0;
+        }
+        Some(bits)
+    }
+
+    /// Inputs written by hand as `actions:ticks` steps separated by commas,
+    /// the actions being `idle` or script names joined by `+`, like `cw:60,cw+dash:1,idle:30`
+    pub fn from_script(seed: u64, script: &str) -> Result<Self, String> {
+        let mut replay = Replay::new(seed);
+        for step in script
+            .split(',')
+            .map(str::trim)
+            .filter(|step| !step.is_empty())
+        {
+            let (actions, count) = step
+                .split_once(':')
+                .ok_or_else(|| format!("step {} is not actions:ticks", step))?;
+            let count: u32 = count
+                .parse()
+                .map_err(|_| format!("bad tick count in step {}", step))?;
+            let mut bits = 0;
+            for name in actions.split('+').filter(|name| *name != "idle") {
+                let action = Action::ALL
+                    .iter()
+                    .find(|action| action.script_name() == name)
+                    .ok_or_else(|| format!("unknown action {}", name))?;
+                bits |= action.bit();
+            }
+            if count > 0 {
+                match replay.runs.last_mut() {
+                    Some((last, last_count)) if *last == bits => *last_count += count,
+                    _ => replay.runs.push((bits, count)),
+                }
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut content = format!("{}\nseed {}\n", REPLAY_HEADER, self.seed);
+        for (bits, count) in &self.runs {
+            content += &format!("{} {}\n", bits, count);
+        }
+        fs::write(path, content)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        match lines.next() {
+            Some(REPLAY_HEADER) => {}
+            Some(header) if header.starts_with("montociel-replay ") => {
+                return Err(invalid("replay of another version of the game"));
+            }
+            _ => return Err(invalid("not a montociel replay")),
+        }
+        let seed = lines
+            .next()
+            .and_then(|line| line.strip_prefix("seed "))
+            .and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| invalid("missing seed"))?;
+        let mut replay = Replay::new(seed);
+        for line in lines {
+            let mut words = line.split_whitespace();
+            let bits = words.next().and_then(|bits| bits.parse().ok());
+            let count = words.next().and_then(|count| count.parse().ok());
+            match (bits, count) {
+                (Some(bits), Some(count)) => replay.runs.push((bits, count)),
+                _ => return Err(invalid("bad tick line")),
+            }
+        }
+        Ok(replay)
+    }
+}
+
+impl FromWorld for ReplaySession {
+    fn from_world(world: &mut World) -> Self {
+        let args = world.get_resource::<Args>().unwrap();
+        if let Some(path) = &args.replay {
+            match Replay::load(path) {
+                Ok(replay) => {
+                    return ReplaySession {
+                        mode: ReplayMode::Playback(ReplayCursor::default()),
+                        replay,
+                        saved: false,
+                    }
+                }
+                Err(e) => error!("could not load replay {}: {}", path.display(), e),
+            }
+        }
+        let mode = match &args.record {
+            Some(path) => ReplayMode::Record(path.clone()),
+            None => ReplayMode::Live,
+        };
+        ReplaySession {
+            mode,
+            replay: Replay::default(),
+            saved: false,
+        }
+    }
+}
+
+impl ReplaySession {
+    /// Play these inputs back instead of reading the devices
+    pub fn playback(replay: Replay) -> Self {
+        ReplaySession {
+            mode: ReplayMode::Playback(ReplayCursor::default()),
+            replay,
+            saved: false,
+        }
+    }
+
+    pub fn is_playback(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playback(_))
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ReplaySession>()
+            .add_startup_system(use_replay_seed.system())
+            .add_system_set(
+                SystemSet::on_enter(AppState::InGame)
+                    .with_system(start_replay.system().after(RngReseed)),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame)
+                    .with_system(read_player_input.system().label(PlayerInputLabel)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(save_replay.system()),
+            )
+            // runs left from the pause menu end without a game over
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(save_replay.system()));
+    }
+}
+
+/// A replay only makes sense with the cloud field it was recorded on
+fn use_replay_seed(session: Res<ReplaySession>, mut rng: ResMut<GameRng>) {
+    if session.is_playback() {
+        rng.set_fixed_seed(session.replay.seed);
+    }
+}
+
+fn start_replay(
+    mut session: ResMut<ReplaySession>,
+    mut input: ResMut<PlayerInput>,
+    rng: Res<GameRng>,
+) {
+    *input = PlayerInput::default();
+    let session = &mut *session;
+    session.saved = false;
+    match &mut session.mode {
+        ReplayMode::Live => {}
+        ReplayMode::Record(_) => session.replay = Replay::new(rng.seed()),
+        ReplayMode::Playback(cursor) => *cursor = ReplayCursor::default(),
+    }
+}
+
+/// Fill `PlayerInput` from the bound devices, or from the replay being played
+fn read_player_input(
+    bindings: Res<Bindings>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<ConnectedGamepads>,
+    mut session: ResMut<ReplaySession>,
+    mut input: ResMut<PlayerInput>,
+) {
+    let session = &mut *session;
+    let bits = match &mut session.mode {
+        ReplayMode::Playback(cursor) => session.replay.next(cursor).unwrap_or_default(),
+        _ => bindings.read(&InputDevices {
+            keys: &keys,
+            mouse: &mouse,
+            gamepad_buttons: &gamepad_buttons,
+            gamepads: &gamepads,
+        }),
+    };
+    input.update(bits);
+    if let ReplayMode::Record(_) = session.mode {
+        session.replay.push(bits);
+    }
+}
+
+fn save_replay(mut session: ResMut<ReplaySession>) {
+    if session.saved {
+        return;
+    }
+    session.saved = true;
+    if let ReplayMode::Record(path) = &session.mode {
+        match session.replay.save(path) {
+            Ok(()) => info!(
+                "saved replay of {} ticks to {}",
+                session.replay.ticks(),
+                path.display()
+            ),
+            Err(e) => error!("could not save replay {}: {}", path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::AppState;
+use crate::Args;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Random number generator every spawner draws from.
+/// It is reseeded at the start of each run, so a given seed reproduces the same cloud field.
+pub struct GameRng {
+    /// seed asked on the command line, a new one is picked for each run otherwise
+    fixed_seed: Option<u64>,
+    seed: u64,
+    rng: StdRng,
+}
+pub struct RngPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct RngReseed;
+
+impl FromWorld for GameRng {
+    fn from_world(world: &mut World) -> Self {
+        let fixed_seed = world.get_resource::<Args>().and_then(|args| args.seed);
+        GameRng::new(fixed_seed)
+    }
+}
+
+impl GameRng {
+    pub fn new(fixed_seed: Option<u64>) -> Self {
+        let seed = fixed_seed.unwrap_or_else(rand::random);
+        GameRng {
+            fixed_seed,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn set_fixed_seed(&mut self, seed: u64) {
+        self.fixed_seed = Some(seed);
+    }
+    pub fn reseed(&mut self) {
+        self.seed = self.fixed_seed.unwrap_or_else(rand::random);
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameRng>().add_system_set(
+            SystemSet::on_enter(AppState::InGame).with_system(reseed_rng.system().label(RngReseed)),
+        );
+    }
+}
+
+fn reseed_rng(mut rng: ResMut<GameRng>) {
+    rng.reseed();
+    info!("starting run with seed {}", rng.seed());
+}
@@ -0,0 +1,25 @@
+use crate::AppState;
+use bevy::ecs::component::Component;
+use bevy::prelude::*;
+
+/// Spawned for a run, despawned with its children when the run is left.
+/// Game over and pause are pushed on top of `AppState::InGame`, so the run is only
+/// left by a restart or a return to the menu.
+pub struct RunEntity;
+
+pub struct RunPlugin;
+
+impl Plugin for RunPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_exit(AppState::InGame).with_system(despawn_with::<RunEntity>.system()),
+        );
+    }
+}
+
+/// Despawn the entities with the marker `T`, and their children
+pub fn despawn_with<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
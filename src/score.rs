@@ -1,54 +1,180 @@
+use crate::main_well;
+use crate::on_tick;
 use crate::AppState;
+use crate::CloudCollision;
+use crate::Dash;
+use crate::GravityWell;
+use crate::Lives;
+use crate::Montociel;
+use crate::RunEntity;
+use crate::SimulationStage;
+use crate::Tuning;
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::f32::consts::PI;
 
-#[derive(Clone, Copy, Debug)]
+/// bounces needed to raise the combo multiplier by one
+const COMBO_STEP: u32 = 3;
+const MAX_COMBO_MULTIPLIER: u32 = 5;
+/// cells of the stamina meter
+const STAMINA_CELLS: usize = 10;
+
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Score {
     score: u32,
+    /// angle travelled around the main well since the start of the run, in radians
+    angle: f32,
+    /// center of the main well, in physics units, and the angle of the sheep around it
+    last_angle: Option<(Vec2, f32)>,
+    /// cloud bounces chained without touching anything else
+    combo: u32,
 }
 struct ScoreUI;
 
-impl Default for Score {
-    fn default() -> Self {
-        Score { score: 0 }
-    }
+/// What killed the sheep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Earth,
+    EvilCloud,
+}
+
+/// Statistics of the current run, shown on the game over screen
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunStats {
+    pub clouds_bounced: u32,
+    pub max_speed: f32,
+    /// seconds survived
+    pub time: f32,
+    pub death_cause: Option<DeathCause>,
 }
 
 impl Score {
-    pub fn incr(&mut self) {
-        //TODO incr more for each turn
-        self.score += 1;
+    /// A cloud bounce, worth more for each turn around the main well and each chained bounce,
+    /// and `bonus` times more with power-ups
+    pub fn incr(&mut self, bonus: u32) {
+        self.score += self.multiplier() * bonus;
+        self.combo += 1;
+    }
+    pub fn break_combo(&mut self) {
+        self.combo = 0;
+    }
+    pub fn get(&self) -> u32 {
+        self.score
+    }
+    /// completed revolutions around the main well, in either direction
+    pub fn laps(&self) -> u32 {
+        (self.angle.abs() / (2. * PI)) as u32
+    }
+    pub fn multiplier(&self) -> u32 {
+        let combo_multiplier = u32::min(1 + self.combo / COMBO_STEP, MAX_COMBO_MULTIPLIER);
+        (1 + self.laps()) * combo_multiplier
     }
     pub fn reset(&mut self) {
-        self.score = 0;
+        *self = Score::default();
+    }
+
+    /// The sheep is at `position` around the well at `center`, in physics units
+    fn track_position(&mut self, position: Vec2, center: Vec2) {
+        let offset = position - center;
+        let angle = f32::atan2(offset.y, offset.x);
+        // switching to another well adds nothing, the laps go on around the new one
+        let last = self
+            .last_angle
+            .filter(|(last_center, _)| *last_center == center);
+        if let Some((_, last_angle)) = last {
+            // shortest way from the last angle, in [-PI, PI]
+            let mut delta = angle - last_angle;
+            if delta > PI {
+                delta -= 2. * PI;
+            } else if delta < -PI {
+                delta += 2. * PI;
+            }
+            self.angle += delta;
+        }
+        self.last_angle = Some((center, angle));
     }
 }
 
 pub struct ScorePlugin;
+/// The score shown during a run
+pub struct ScoreUIPlugin;
 
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<Score>()
-            .add_system_set(
-                SystemSet::on_enter(AppState::InGame).with_system(setup_score_ui.system()),
-            )
-            .add_system_set(
-                SystemSet::on_update(AppState::InGame).with_system(update_score_ui.system()),
+            .init_resource::<RunStats>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(reset_run.system()))
+            .add_system_set_to_stage(
+                SimulationStage,
+                on_tick(AppState::InGame).with_system(track_run.system().after(CloudCollision)),
             );
     }
 }
 
-fn update_score_ui(score: Res<Score>, mut query: Query<&mut Text, With<ScoreUI>>) {
-    for mut text in query.iter_mut() {
-        text.sections[1].value = format!("{:.2}", score.score);
+impl Plugin for ScoreUIPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::InGame).with_system(setup_score_ui.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame).with_system(update_score_ui.system()),
+        );
     }
 }
 
-fn setup_score_ui(
-    mut commands: Commands,
+fn reset_run(mut score: ResMut<Score>, mut stats: ResMut<RunStats>) {
+    score.reset();
+    *stats = RunStats::default();
+}
+
+fn track_run(
     mut score: ResMut<Score>,
-    asset_server: Res<AssetServer>,
+    mut stats: ResMut<RunStats>,
+    integration_parameters: Res<IntegrationParameters>,
+    tuning: Res<Tuning>,
+    wells: Query<&GravityWell>,
+    montociel_info: Query<(&RigidBodyPosition, &RigidBodyVelocity), With<Montociel>>,
 ) {
-    score.reset();
+    for (pos, vel) in montociel_info.iter() {
+        let position = pos.position.translation.vector.into();
+        let center =
+            main_well(wells.iter(), &tuning, position).map_or(Vec2::ZERO, |well| well.position);
+        score.track_position(position, center);
+        stats.max_speed = f32::max(stats.max_speed, vel.linvel.norm());
+    }
+    stats.time += integration_parameters.dt;
+}
+
+fn update_score_ui(
+    score: Res<Score>,
+    lives: Res<Lives>,
+    tuning: Res<Tuning>,
+    dashes: Query<&Dash, With<Montociel>>,
+    mut query: Query<&mut Text, With<ScoreUI>>,
+) {
+    for mut text in query.iter_mut() {
+        text.sections[1].value = format!("{:.2}", score.score);
+        text.sections[3].value = format!("{}", score.laps());
+        text.sections[5].value = format!("{}", score.multiplier());
+        text.sections[7].value = format!("{}", lives.0);
+        if let Some(dash) = dashes.iter().next() {
+            let full = (dash.stamina * STAMINA_CELLS as f32).round() as usize;
+            text.sections[9].value = format!(
+                "{}{}",
+                "█".repeat(full),
+                "░".repeat(STAMINA_CELLS.saturating_sub(full))
+            );
+            // gold when a dash is ready
+            text.sections[9].style.color = if dash.is_ready(&tuning) {
+                Color::GOLD
+            } else {
+                Color::GRAY
+            };
+        }
+    }
+}
+
+fn setup_score_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn_bundle(TextBundle {
             style: Style {
@@ -75,10 +201,75 @@ fn setup_score_ui(
                             color: Color::GOLD,
                         },
                     },
+                    TextSection {
+                        value: "  Laps: ".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "0".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::GOLD,
+                        },
+                    },
+                    TextSection {
+                        value: "  x".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "1".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::GOLD,
+                        },
+                    },
+                    TextSection {
+                        value: "  Lives: ".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::GOLD,
+                        },
+                    },
+                    TextSection {
+                        value: "  Dash: ".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::GOLD,
+                        },
+                    },
                 ],
                 ..Default::default()
             },
             ..Default::default()
         })
-        .insert(ScoreUI);
+        .insert(ScoreUI)
+        .insert(RunEntity);
 }
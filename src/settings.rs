@@ -0,0 +1,83 @@
+use crate::AppState;
+use crate::Bindings;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Player preferences
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Settings {
+    /// pause the run when the window loses focus
+    pub pause_on_focus_loss: bool,
+}
+pub struct SettingsPlugin;
+
+/// Content of the settings file
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SettingsFile {
+    settings: Settings,
+    bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            pause_on_focus_loss: true,
+        }
+    }
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let SettingsFile { settings, bindings } = load_settings();
+        app.insert_resource(settings)
+            .insert_resource(bindings)
+            .add_system_set(
+                SystemSet::on_exit(AppState::Settings).with_system(save_settings.system()),
+            );
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("montociel").join("settings.ron"))
+}
+
+fn load_settings() -> SettingsFile {
+    settings_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| match ron::de::from_str(&content) {
+            Ok(settings) => Some(settings),
+            Err(e) => {
+                error!("could not read settings: {}", e);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+fn save_settings(settings: Res<Settings>, bindings: Res<Bindings>) {
+    let path = match settings_path() {
+        Some(path) => path,
+        None => return,
+    };
+    let settings_file = SettingsFile {
+        settings: settings.clone(),
+        bindings: bindings.clone(),
+    };
+    let content = match ron::ser::to_string_pretty(&settings_file, Default::default()) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("could not serialize settings: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, content))
+    {
+        error!("could not save settings to {}: {}", path.display(), e);
+    }
+}
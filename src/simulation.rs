@@ -0,0 +1,212 @@
+use crate::AppState;
+use bevy::app::Events;
+use bevy::ecs::schedule::{ShouldRun, Stage};
+use bevy::prelude::*;
+use bevy_rapier2d::physics::{
+    self, JointsEntityMap, ModificationTracker, PhysicsHooksWithQueryObject, PhysicsStages,
+    PhysicsSystems, SimulationToRenderTime,
+};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::dynamics::{CCDSolver, IslandManager, JointSet};
+use bevy_rapier2d::rapier::geometry::{BroadPhase, NarrowPhase};
+use bevy_rapier2d::rapier::pipeline::{PhysicsPipeline, QueryPipeline};
+
+/// ticks run in a single frame at most, the game slows down rather than freezing
+/// when frames take too long
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+/// Stage running the gameplay and the physics step, tick after tick of `IntegrationParameters::dt`.
+/// It runs right after `CoreStage::Update`, as many times as needed to keep up with the time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimulationStage;
+
+/// How many ticks the simulation runs each frame
+pub enum SimulationClock {
+    /// as many ticks as the time elapsed since the last frame
+    RealTime {
+        /// time not simulated yet, less than a tick
+        lag: f32,
+    },
+    /// one tick each frame, for the headless runs
+    Lockstep,
+}
+
+/// The rapier plugin, with the physics step moved to the `SimulationStage`
+pub struct SimulationPlugin;
+
+/// The rapier systems handing the new bodies, colliders and joints to the physics world.
+/// The collider attachment needs the commands of the first stage applied.
+struct PhysicsAttach(Schedule);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+enum PhysicsAttachStage {
+    Create,
+    Attach,
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        SimulationClock::RealTime { lag: 0. }
+    }
+}
+
+impl Default for PhysicsAttach {
+    fn default() -> Self {
+        let mut schedule = Schedule::default();
+        schedule
+            .add_stage(
+                PhysicsAttachStage::Create,
+                SystemStage::parallel()
+                    .with_system(
+                        physics::attach_bodies_and_colliders_system
+                            .system()
+                            .label(PhysicsSystems::AttachBodiesAndColliders),
+                    )
+                    .with_system(
+                        physics::create_joints_system
+                            .system()
+                            .label(PhysicsSystems::CreateJoints),
+                    ),
+            )
+            .add_stage(
+                PhysicsAttachStage::Attach,
+                SystemStage::parallel().with_system(
+                    physics::finalize_collider_attach_to_bodies
+                        .system()
+                        .label(PhysicsSystems::FinalizeColliderAttachToBodies),
+                ),
+            );
+        PhysicsAttach(schedule)
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SimulationClock>()
+            .add_stage_after(
+                CoreStage::Update,
+                SimulationStage,
+                SystemStage::parallel().with_run_criteria(simulation_clock.system()),
+            )
+            .add_stage_before(
+                CoreStage::PostUpdate,
+                PhysicsStages::SyncTransforms,
+                SystemStage::parallel(),
+            )
+            .insert_resource(PhysicsPipeline::new())
+            .insert_resource(QueryPipeline::new())
+            .insert_resource(RapierConfiguration::default())
+            .insert_resource(IntegrationParameters::default())
+            .insert_resource(BroadPhase::new())
+            .insert_resource(NarrowPhase::new())
+            .insert_resource(IslandManager::new())
+            .insert_resource(JointSet::new())
+            .insert_resource(CCDSolver::new())
+            .insert_resource(Events::<IntersectionEvent>::default())
+            .insert_resource(Events::<ContactEvent>::default())
+            .insert_resource(SimulationToRenderTime::default())
+            .insert_resource(JointsEntityMap::default())
+            .insert_resource(ModificationTracker::default())
+            .insert_resource(PhysicsHooksWithQueryObject::<NoUserData>(Box::new(())))
+            .insert_resource(PhysicsAttach::default())
+            // the bodies spawned by a tick collide from the next one, whatever the frame rate
+            .add_system_to_stage(
+                SimulationStage,
+                attach_to_physics.exclusive_system().at_start(),
+            )
+            // rapier leaves its events to the app, keep those of the last two ticks
+            .add_system_to_stage(
+                SimulationStage,
+                Events::<ContactEvent>::update_system
+                    .system()
+                    .before(PhysicsSystems::StepWorld),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                Events::<IntersectionEvent>::update_system
+                    .system()
+                    .before(PhysicsSystems::StepWorld),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                physics::step_world_system::<NoUserData>
+                    .system()
+                    .label(PhysicsSystems::StepWorld),
+            )
+            .add_system_to_stage(
+                PhysicsStages::SyncTransforms,
+                physics::sync_transforms
+                    .system()
+                    .label(PhysicsSystems::SyncTransforms),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                physics::collect_removals
+                    .system()
+                    .label(PhysicsSystems::CollectRemovals),
+            );
+    }
+}
+
+/// Systems of the `SimulationStage` running in this state.
+/// `SystemSet::on_update` can't be used there, it needs the state driver which lives in `CoreStage::Update`.
+pub fn on_tick(state: AppState) -> SystemSet {
+    SystemSet::new().with_run_criteria(
+        (move |current: Res<State<AppState>>| {
+            if *current.current() == state {
+                ShouldRun::Yes
+            } else {
+                ShouldRun::No
+            }
+        })
+        .system(),
+    )
+}
+
+/// Runs at the start of each tick
+fn attach_to_physics(world: &mut World) {
+    world.resource_scope(|world, mut attach: Mut<PhysicsAttach>| attach.0.run(world));
+}
+
+fn simulation_clock(
+    time: Res<Time>,
+    integration_parameters: Res<IntegrationParameters>,
+    state: Res<State<AppState>>,
+    mut clock: ResMut<SimulationClock>,
+    mut ticks: Local<u32>,
+) -> ShouldRun {
+    // the last tick queued a state transition, like a game over, wait for it
+    if *ticks > 0 && state.is_changed() {
+        *ticks = 0;
+        return ShouldRun::No;
+    }
+    let dt = integration_parameters.dt;
+    match &mut *clock {
+        SimulationClock::Lockstep => {
+            if *ticks == 0 {
+                *ticks = 1;
+                ShouldRun::YesAndCheckAgain
+            } else {
+                *ticks = 0;
+                ShouldRun::No
+            }
+        }
+        SimulationClock::RealTime { lag } => {
+            if *ticks == 0 {
+                *lag += time.delta_seconds();
+            }
+            if *lag >= dt && *ticks < MAX_TICKS_PER_FRAME {
+                *lag -= dt;
+                *ticks += 1;
+                ShouldRun::YesAndCheckAgain
+            } else {
+                if *ticks == MAX_TICKS_PER_FRAME {
+                    // too far behind, let go of the time that could not be simulated
+                    *lag = 0.;
+                }
+                *ticks = 0;
+                ShouldRun::No
+            }
+        }
+    }
+}
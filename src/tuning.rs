@@ -0,0 +1,293 @@
+use crate::GravityLaw;
+use crate::PatternWeights;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::FileAssetIo;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+/// The asset server picks the loader from what follows a dot in the file name,
+/// a plain `tuning.ron` would never reach the `TuningLoader`
+const TUNING_FILE: &str = "gameplay.tuning.ron";
+
+/// Gameplay constants, loaded from `assets/gameplay.tuning.ron` and reloaded when the file changes.
+/// Radii are in pixels, distances from the wells in physics units, speeds in physics units per second.
+/// `thrust_power` and `brake` are the exception, they apply each tick.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "efbcdb04-a1f5-4ee9-90fc-dc1f320d78fd"]
+#[serde(default)]
+pub struct Tuning {
+    /// pull of the earth on the sheep
+    pub gravity: f32,
+    /// how the pull of the earth, and of the wells without their own law, weakens with distance
+    pub gravity_law: GravityLaw,
+    /// rate at which the air slows the sheep down, per second
+    pub air_drag: f32,
+    /// height over which the air gets thinner by a factor e, none when 0
+    pub atmosphere_height: f32,
+    pub max_speed: f32,
+    /// speed added each tick by thrusting
+    pub thrust_power: f32,
+    /// part of the sheep velocity kept each tick while braking
+    pub brake: f32,
+    /// speed added along the tangent by a dash
+    pub dash_speed: f32,
+    /// speed cap for `dash_duration` seconds after a dash
+    pub dash_max_speed: f32,
+    pub dash_duration: f32,
+    /// seconds between two dashes
+    pub dash_cooldown: f32,
+    /// stamina spent by a dash, out of 1
+    pub dash_cost: f32,
+    /// stamina given back by a cloud bounce
+    pub dash_refill: f32,
+    /// speed given by a cloud bounce
+    pub jump_power: f32,
+    /// hits taken before the run ends
+    pub lives: u32,
+    /// seconds after a hit during which hazards only push the sheep away
+    pub invulnerability: f32,
+    /// speed the sheep is thrown away from a hazard with
+    pub knockback: f32,
+    pub montociel_radius: f32,
+    pub earth_radius: f32,
+    pub cloud_radius: f32,
+    /// clouds around the earth at the start of a run
+    pub cloud_belt_count: u32,
+    /// distance of the starting clouds from the center of their well, in physics units,
+    /// for a well the size of the earth
+    pub cloud_belt_distance: f32,
+    pub cloud_belt_max_speed: f32,
+    /// clouds spawned every `new_cloud_period` seconds
+    pub new_cloud_count: u32,
+    pub new_cloud_period: f32,
+    /// distance of the new clouds from the center of their well, in physics units,
+    /// for a well the size of the earth
+    pub new_cloud_distance: f32,
+    pub new_cloud_min_speed: f32,
+    pub new_cloud_max_speed: f32,
+    /// chance of a cloud to be evil at the start of a run
+    pub base_evil_chance: f32,
+    /// evil chance added for each point of score
+    pub evil_chance_per_point: f32,
+    /// evil chance added for each second survived
+    pub evil_chance_per_second: f32,
+    /// cap of the evil chance, so there are always some clouds left to bounce on
+    pub max_evil_chance: f32,
+    /// chance of a cloud to be a power-up, evil clouds never are
+    pub power_up_chance: f32,
+    /// odds of each motion pattern for the spawned clouds
+    pub cloud_patterns: PatternWeights,
+    /// clouds further than this from the center of every well are removed
+    pub play_area_radius: f32,
+}
+#[derive(Default)]
+pub struct TuningLoader;
+struct TuningHandle(Handle<Tuning>);
+pub struct TuningPlugin;
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            gravity: 100.,
+            gravity_law: GravityLaw::Constant,
+            // 5% of the speed lost each tick at 60 ticks per second
+            air_drag: 3.08,
+            atmosphere_height: 0.,
+            max_speed: 70.,
+            thrust_power: 0.8,
+            brake: 0.9,
+            dash_speed: 30.,
+            dash_max_speed: 110.,
+            dash_duration: 0.4,
+            dash_cooldown: 1.,
+            dash_cost: 0.35,
+            dash_refill: 0.1,
+            jump_power: 70.,
+            lives: 3,
+            invulnerability: 2.,
+            knockback: 40.,
+            montociel_radius: 30.,
+            earth_radius: 60.,
+            cloud_radius: 15.,
+            cloud_belt_count: 10,
+            cloud_belt_distance: 10.,
+            cloud_belt_max_speed: 5.,
+            new_cloud_count: 4,
+            new_cloud_period: 2.,
+            new_cloud_distance: 1.,
+            new_cloud_min_speed: 1.,
+            new_cloud_max_speed: 5.,
+            base_evil_chance: 0.,
+            evil_chance_per_point: 0.01,
+            evil_chance_per_second: 0.005,
+            max_evil_chance: 0.5,
+            power_up_chance: 0.1,
+            cloud_patterns: PatternWeights::default(),
+            play_area_radius: 1200.,
+        }
+    }
+}
+
+impl Tuning {
+    /// Read `gameplay.tuning.ron` from the assets folder right away, for apps without an asset server
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_file() -> Result<Self, String> {
+        let path = FileAssetIo::get_root_path()
+            .join("assets")
+            .join(TUNING_FILE);
+        let bytes = std::fs::read(&path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let tuning: Tuning = ron::de::from_bytes(&bytes)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))?;
+        tuning
+            .validate()
+            .map_err(|e| format!("bad tuning in {}: {}", path.display(), e))?;
+        Ok(tuning)
+    }
+
+    /// Values the game would crash on or turn into NaN, like an empty range of speeds
+    /// or clouds spawned right at the center of a well
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("max_speed", self.max_speed),
+            ("dash_max_speed", self.dash_max_speed),
+            ("montociel_radius", self.montociel_radius),
+            ("earth_radius", self.earth_radius),
+            ("cloud_radius", self.cloud_radius),
+            ("cloud_belt_distance", self.cloud_belt_distance),
+            ("cloud_belt_max_speed", self.cloud_belt_max_speed),
+            ("new_cloud_period", self.new_cloud_period),
+            ("new_cloud_distance", self.new_cloud_distance),
+            ("new_cloud_max_speed", self.new_cloud_max_speed),
+            ("play_area_radius", self.play_area_radius),
+        ];
+        let non_negative = [
+            ("gravity", self.gravity),
+            ("air_drag", self.air_drag),
+            ("atmosphere_height", self.atmosphere_height),
+            ("thrust_power", self.thrust_power),
+            ("dash_speed", self.dash_speed),
+            ("dash_duration", self.dash_duration),
+            ("dash_cooldown", self.dash_cooldown),
+            ("jump_power", self.jump_power),
+            ("invulnerability", self.invulnerability),
+            ("knockback", self.knockback),
+            ("new_cloud_min_speed", self.new_cloud_min_speed),
+        ];
+        if let Some((name, _)) = positive.iter().find(|(_, value)| !is_positive(*value)) {
+            return Err(format!("{} must be positive", name));
+        }
+        if let Some((name, _)) = non_negative
+            .iter()
+            .find(|(_, value)| !is_non_negative(*value))
+        {
+            return Err(format!("{} can't be negative", name));
+        }
+        if !(0. ..=1.).contains(&self.brake) {
+            return Err("brake must be between 0 and 1".to_string());
+        }
+        let chances = [
+            ("base_evil_chance", self.base_evil_chance),
+            ("max_evil_chance", self.max_evil_chance),
+            ("power_up_chance", self.power_up_chance),
+        ];
+        if let Some((name, _)) = chances
+            .iter()
+            .find(|(_, chance)| !(0. ..=1.).contains(chance))
+        {
+            return Err(format!("{} must be between 0 and 1", name));
+        }
+        let slopes = [
+            ("evil_chance_per_point", self.evil_chance_per_point),
+            ("evil_chance_per_second", self.evil_chance_per_second),
+        ];
+        if let Some((name, _)) = slopes.iter().find(|(_, slope)| !slope.is_finite()) {
+            return Err(format!("{} must be a number", name));
+        }
+        if self.new_cloud_min_speed >= self.new_cloud_max_speed {
+            return Err("new_cloud_min_speed must be less than new_cloud_max_speed".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.
+}
+
+fn is_non_negative(value: f32) -> bool {
+    value.is_finite() && value >= 0.
+}
+
+impl AssetLoader for TuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let tuning: Tuning = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Tuning>()
+            .add_asset::<Tuning>()
+            .init_asset_loader::<TuningLoader>()
+            .add_startup_system(load_tuning.system())
+            .add_system(apply_tuning.system());
+    }
+}
+
+fn load_tuning(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // hot reload, there is no file system to watch on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(e) = asset_server.watch_for_changes() {
+        error!("could not watch assets for changes: {:?}", e);
+    }
+    commands.insert_resource(TuningHandle(asset_server.load(TUNING_FILE)));
+}
+
+/// Copy the tuning asset to the `Tuning` resource each time it is (re)loaded
+fn apply_tuning(
+    mut events: EventReader<AssetEvent<Tuning>>,
+    assets: Res<Assets<Tuning>>,
+    handle: Option<Res<TuningHandle>>,
+    mut tuning: ResMut<Tuning>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+                if *changed != handle.0 {
+                    continue;
+                }
+                if let Some(loaded) = assets.get(changed) {
+                    // a mistake in the file while the game runs keeps the last good tuning
+                    match loaded.validate() {
+                        Ok(()) => {
+                            *tuning = loaded.clone();
+                            info!("tuning loaded");
+                        }
+                        Err(e) => error!("bad tuning, not applied: {}", e),
+                    }
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
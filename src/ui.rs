@@ -1,91 +1,665 @@
+use crate::despawn_with;
+use crate::Action;
 use crate::AppState;
+use crate::Binding;
+use crate::Bindings;
+use crate::ConnectedGamepads;
+use crate::DeathCause;
+use crate::HighScoreUpdate;
+use crate::HighScores;
+use crate::InputDevices;
+use crate::RunStats;
+use crate::Score;
+use crate::Settings;
+use bevy::app::AppExit;
+use bevy::input::gamepad::GamepadButton;
 use bevy::prelude::*;
 
 pub struct UIPlugin;
+/// Root node of the menu, game over, pause, settings and controls screens
+struct UIRoot;
+
+/// Action of the controls screen waiting for a key or button
+#[derive(Default)]
+struct Rebinding {
+    action: Option<Action>,
+    /// skip the frame of the click on the rebind button
+    listening: bool,
+    status: String,
+}
+
+/// Texts of the controls screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ControlsText {
+    Bindings(Action),
+    Status,
+}
+
+struct ButtonMaterials {
+    normal: Handle<ColorMaterial>,
+    hovered: Handle<ColorMaterial>,
+    pressed: Handle<ColorMaterial>,
+    panel: Handle<ColorMaterial>,
+}
+
+/// What happens when a button is clicked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ButtonAction {
+    Play,
+    /// start a new run, from the game over screen or the pause menu
+    Retry,
+    Menu,
+    Quit,
+    Resume,
+    Settings,
+    /// leave the settings or the controls
+    Back,
+    TogglePauseOnFocusLoss,
+    Controls,
+    Rebind(Action),
+    ResetBindings,
+}
+
+impl FromWorld for ButtonMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        ButtonMaterials {
+            normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+            pressed: materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
+            panel: materials.add(Color::rgba(0.1, 0.1, 0.2, 0.7).into()),
+        }
+    }
+}
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_button.system()))
+        app.init_resource::<ButtonMaterials>()
+            .init_resource::<Rebinding>()
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu.system()))
+            .add_system_set(SystemSet::on_resume(AppState::Menu).with_system(setup_menu.system()))
             .add_system_set(
-                SystemSet::on_enter(AppState::GameOver).with_system(setup_button.system()),
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(setup_game_over.system().after(HighScoreUpdate)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Paused).with_system(setup_pause_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_resume(AppState::Paused).with_system(setup_pause_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Settings).with_system(setup_settings.system()),
+            )
+            .add_system_set(
+                SystemSet::on_resume(AppState::Settings).with_system(setup_settings.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Settings).with_system(update_settings_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Controls).with_system(setup_controls.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Controls)
+                    .with_system(rebind_controls.system())
+                    .with_system(update_controls_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Menu).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_pause(AppState::Menu).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::GameOver).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Paused).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_pause(AppState::Paused).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Settings).with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_pause(AppState::Settings)
+                    .with_system(despawn_with::<UIRoot>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Controls).with_system(despawn_with::<UIRoot>.system()),
             )
             .add_system(button_system.system());
     }
 }
 
 fn button_system(
-    mut commands: Commands,
-    mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>)>,
-    entities: Query<Entity, Without<bevy::render::camera::Camera>>,
-    button: Query<Entity, With<Button>>,
+    button_materials: Res<ButtonMaterials>,
+    mut interaction_query: Query<
+        (&Interaction, &ButtonAction, &mut Handle<ColorMaterial>),
+        (Changed<Interaction>, With<Button>),
+    >,
     mut state: ResMut<State<AppState>>,
+    mut settings: ResMut<Settings>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut app_exit_events: EventWriter<AppExit>,
 ) {
-    for interaction in interaction_query.iter_mut() {
+    for (interaction, action, mut material) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
-                match state.current() {
-                    AppState::Menu => {
-                        for button in button.iter() {
-                            commands.entity(button).despawn_recursive();
-                        }
-                        state.set(AppState::InGame).unwrap();
+                *material = button_materials.pressed.clone();
+                match action {
+                    // another transition may already be queued this frame, like Escape's
+                    ButtonAction::Play => {
+                        let _ = state.set(AppState::InGame);
+                    }
+                    ButtonAction::Retry | ButtonAction::Menu => {
+                        let next = if *action == ButtonAction::Retry {
+                            AppState::InGame
+                        } else {
+                            AppState::Menu
+                        };
+                        // replace the whole stack, leaving the run despawns it
+                        let _ = state.replace(next);
+                    }
+                    ButtonAction::Resume | ButtonAction::Back => {
+                        let _ = state.pop();
+                    }
+                    ButtonAction::Settings => {
+                        let _ = state.push(AppState::Settings);
                     }
-                    AppState::GameOver => {
-                        //despawn all entities
-                        for entity in entities.iter() {
-                            commands.entity(entity).despawn();
-                        }
-                        for button in button.iter() {
-                            commands.entity(button).despawn_recursive();
-                        }
-                        state.set(AppState::InGame).unwrap();
+                    ButtonAction::TogglePauseOnFocusLoss => {
+                        settings.pause_on_focus_loss = !settings.pause_on_focus_loss;
                     }
-                    AppState::InGame => panic!(),
+                    ButtonAction::Controls => {
+                        let _ = state.push(AppState::Controls);
+                    }
+                    ButtonAction::Rebind(action) => {
+                        rebinding.action = Some(*action);
+                        rebinding.listening = false;
+                        rebinding.status = format!("Press a key or button for {}", action.name());
+                    }
+                    ButtonAction::ResetBindings => {
+                        *bindings = Bindings::default();
+                        rebinding.action = None;
+                        rebinding.status = "Controls reset to defaults".to_string();
+                    }
+                    ButtonAction::Quit => app_exit_events.send(AppExit),
                 }
             }
-            Interaction::Hovered => {}
-            Interaction::None => {}
+            Interaction::Hovered => *material = button_materials.hovered.clone(),
+            Interaction::None => *material = button_materials.normal.clone(),
         }
     }
 }
 
-fn setup_button(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    state: Res<State<AppState>>,
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    text: &str,
+    action: ButtonAction,
 ) {
-    let text = match state.current() {
-        AppState::Menu => "Play!",
-        AppState::InGame => panic!(),
-        AppState::GameOver => "Revive!",
-    };
-    commands
+    parent
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-                // center button
-                margin: Rect::all(Val::Auto),
+                min_size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                margin: Rect::all(Val::Px(10.0)),
+                padding: Rect {
+                    left: Val::Px(20.0),
+                    right: Val::Px(20.0),
+                    ..Default::default()
+                },
                 // horizontally center child text
                 justify_content: JustifyContent::Center,
                 // vertically center child text
                 align_items: AlignItems::Center,
                 ..Default::default()
             },
-            //material: button_materials.normal.clone(),
+            material: button_materials.normal.clone(),
             ..Default::default()
         })
+        .insert(action)
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                text: Text::with_section(
-                    text,
-                    TextStyle {
-                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                        font_size: 40.0,
-                        color: Color::rgb(0.9, 0.9, 0.9),
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        text,
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(action);
+        });
+}
+
+/// Invisible full screen node centering its children
+fn root_node() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        visible: Visible {
+            is_visible: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Centered panel with a title, its content is laid out from top to bottom
+fn spawn_panel(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    title: &str,
+    spawn_content: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn_bundle(root_node())
+        .insert(UIRoot)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        // ui y axis points up, reverse the column to lay it out top to bottom
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(20.0)),
+                        ..Default::default()
                     },
-                    Default::default(),
-                ),
+                    material: button_materials.panel.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            title,
+                            TextStyle {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                font_size: 60.0,
+                                color: Color::WHITE,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                    spawn_content(parent);
+                });
+        });
+}
+
+/// Invisible node laying out buttons side by side
+fn button_row() -> NodeBundle {
+    NodeBundle {
+        visible: Visible {
+            is_visible: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn setup_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+) {
+    let mut root = root_node();
+    // ui y axis points up, reverse the column to lay it out top to bottom
+    root.style.flex_direction = FlexDirection::ColumnReverse;
+    commands
+        .spawn_bundle(root)
+        .insert(UIRoot)
+        .with_children(|parent| {
+            for (text, action) in [
+                ("Play!", ButtonAction::Play),
+                ("Settings", ButtonAction::Settings),
+            ] {
+                spawn_button(parent, &asset_server, &button_materials, text, action);
+            }
+        });
+}
+
+fn setup_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    high_scores: Res<HighScores>,
+) {
+    let stats_style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 25.0,
+        color: Color::WHITE,
+    };
+    let death_cause = match stats.death_cause {
+        Some(DeathCause::Earth) => "the earth",
+        Some(DeathCause::EvilCloud) => "an evil cloud",
+        None => "nothing",
+    };
+    let mut sections = vec![TextSection {
+        value: format!(
+            "Score      {:>8}\nClouds     {:>8}\nLaps       {:>8}\nMax speed  {:>8.1}\nTime       {:>7.1}s\nKilled by  {}\n",
+            score.get(),
+            stats.clouds_bounced,
+            score.laps(),
+            stats.max_speed,
+            stats.time,
+            death_cause,
+        ),
+        style: stats_style.clone(),
+    }];
+    if high_scores.is_new_best() {
+        sections.push(TextSection {
+            value: "New personal best!".to_string(),
+            style: TextStyle {
+                color: Color::GOLD,
+                ..stats_style
+            },
+        });
+    }
+
+    spawn_panel(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        "You died",
+        |parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(20.0)),
+                    ..Default::default()
+                },
+                text: Text {
+                    sections,
+                    ..Default::default()
+                },
                 ..Default::default()
             });
-        });
+            parent.spawn_bundle(button_row()).with_children(|parent| {
+                for (text, action) in [
+                    ("Retry", ButtonAction::Retry),
+                    ("Menu", ButtonAction::Menu),
+                    ("Quit", ButtonAction::Quit),
+                ] {
+                    spawn_button(parent, &asset_server, &button_materials, text, action);
+                }
+            });
+        },
+    );
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+) {
+    spawn_panel(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        "Paused",
+        |parent| {
+            for (text, action) in [
+                ("Resume", ButtonAction::Resume),
+                ("Restart", ButtonAction::Retry),
+                ("Settings", ButtonAction::Settings),
+                ("Quit to Menu", ButtonAction::Menu),
+            ] {
+                spawn_button(parent, &asset_server, &button_materials, text, action);
+            }
+        },
+    );
+}
+
+fn pause_on_focus_loss_text(settings: &Settings) -> String {
+    let on_off = if settings.pause_on_focus_loss {
+        "on"
+    } else {
+        "off"
+    };
+    format!("Pause on focus loss: {}", on_off)
+}
+
+fn setup_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    settings: Res<Settings>,
+) {
+    spawn_panel(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        "Settings",
+        |parent| {
+            spawn_button(
+                parent,
+                &asset_server,
+                &button_materials,
+                &pause_on_focus_loss_text(&settings),
+                ButtonAction::TogglePauseOnFocusLoss,
+            );
+            spawn_button(
+                parent,
+                &asset_server,
+                &button_materials,
+                "Controls",
+                ButtonAction::Controls,
+            );
+            spawn_button(
+                parent,
+                &asset_server,
+                &button_materials,
+                "Back",
+                ButtonAction::Back,
+            );
+        },
+    );
+}
+
+fn update_settings_ui(settings: Res<Settings>, mut texts: Query<(&mut Text, &ButtonAction)>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (mut text, action) in texts.iter_mut() {
+        if *action == ButtonAction::TogglePauseOnFocusLoss {
+            text.sections[0].value = pause_on_focus_loss_text(&settings);
+        }
+    }
+}
+
+fn bindings_text(bindings: &Bindings, action: Action) -> String {
+    let names: Vec<String> = bindings.of(action).map(Binding::name).collect();
+    if names.is_empty() {
+        "unbound".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+fn setup_controls(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    bindings: Res<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    *rebinding = Rebinding::default();
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+    spawn_panel(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        "Controls",
+        |parent| {
+            for action in Action::ALL {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        visible: Visible {
+                            is_visible: false,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle {
+                                style: Style {
+                                    min_size: Size::new(Val::Px(550.0), Val::Auto),
+                                    ..Default::default()
+                                },
+                                text: Text {
+                                    sections: vec![
+                                        TextSection {
+                                            value: format!("{:<26}", action.name()),
+                                            style: text_style.clone(),
+                                        },
+                                        TextSection {
+                                            value: bindings_text(&bindings, action),
+                                            style: TextStyle {
+                                                color: Color::GOLD,
+                                                ..text_style.clone()
+                                            },
+                                        },
+                                    ],
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(ControlsText::Bindings(action));
+                        spawn_button(
+                            parent,
+                            &asset_server,
+                            &button_materials,
+                            "Rebind",
+                            ButtonAction::Rebind(action),
+                        );
+                    });
+            }
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", text_style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(ControlsText::Status);
+            parent.spawn_bundle(button_row()).with_children(|parent| {
+                for (text, action) in [
+                    ("Reset to defaults", ButtonAction::ResetBindings),
+                    ("Back", ButtonAction::Back),
+                ] {
+                    spawn_button(parent, &asset_server, &button_materials, text, action);
+                }
+            });
+        },
+    );
+}
+
+/// Bind the next key or button pressed to the action being rebound
+fn rebind_controls(
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<ConnectedGamepads>,
+    buttons: Query<&Interaction, With<Button>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let escape = keys.just_pressed(KeyCode::Escape);
+    if escape {
+        keys.reset(KeyCode::Escape);
+    }
+    let action = match rebinding.action {
+        Some(action) => action,
+        None => {
+            if escape {
+                // a Back click may have queued it already
+                let _ = state.pop();
+            }
+            return;
+        }
+    };
+    if escape {
+        rebinding.action = None;
+        rebinding.status = "Rebinding cancelled".to_string();
+        return;
+    }
+    if !rebinding.listening {
+        rebinding.listening = true;
+        return;
+    }
+    let binding = match Binding::just_pressed(&InputDevices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepads: &gamepads,
+    }) {
+        Some(binding) => binding,
+        None => return,
+    };
+    // a click on a button of the screen, like Back, is not meant as a binding
+    let on_button = buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Clicked);
+    if on_button && matches!(binding, Binding::Mouse(_)) {
+        return;
+    }
+    rebinding.action = None;
+    // P pauses the game, Escape already cancelled
+    if binding == Binding::Key(KeyCode::P) {
+        rebinding.status = format!("{} is reserved for pause", binding.name());
+        return;
+    }
+    rebinding.status = match bindings.bind(action, binding) {
+        Some(conflict) => format!(
+            "{} bound to {}, {} lost it",
+            binding.name(),
+            action.name(),
+            conflict.name()
+        ),
+        None => format!("{} bound to {}", binding.name(), action.name()),
+    };
+}
+
+fn update_controls_ui(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&mut Text, &ControlsText)>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (mut text, controls_text) in texts.iter_mut() {
+        match controls_text {
+            ControlsText::Bindings(action) => {
+                text.sections[1].value = if rebinding.action == Some(*action) {
+                    "...".to_string()
+                } else {
+                    bindings_text(&bindings, *action)
+                };
+            }
+            ControlsText::Status => text.sections[0].value = rebinding.status.clone(),
+        }
+    }
 }
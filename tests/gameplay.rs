@@ -0,0 +1,1107 @@
+use bevy::app::Events;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use montociel::*;
+
+/// A headless run with no random clouds, the physics pipeline frozen
+/// so contacts only happen when a test sends them.
+fn frozen_run() -> App {
+    frozen_level(Level::named("earth").unwrap())
+}
+
+fn frozen_level(level: Level) -> App {
+    frozen_tuned(
+        level,
+        Tuning {
+            cloud_belt_count: 0,
+            // the first hit ends the run
+            lives: 1,
+            ..Default::default()
+        },
+    )
+}
+
+fn frozen_tuned(level: Level, tuning: Tuning) -> App {
+    let mut app = App::build();
+    app.insert_resource(tuning)
+        .insert_resource(level)
+        .add_plugin(HeadlessPlugin);
+    app.world_mut()
+        .get_resource_mut::<RapierConfiguration>()
+        .unwrap()
+        .physics_pipeline_active = false;
+    let mut app = app.app;
+    // enter the run
+    app.update();
+    app
+}
+
+/// A headless run with the physics on and random clouds from a fixed seed,
+/// ticked by this clock
+fn live_run(clock: SimulationClock) -> App {
+    live_tuned(
+        Tuning {
+            // long enough to meet a few clouds
+            lives: 100,
+            ..Default::default()
+        },
+        clock,
+    )
+}
+
+fn live_tuned(tuning: Tuning, clock: SimulationClock) -> App {
+    let mut app = App::build();
+    app.insert_resource(tuning)
+        .insert_resource(Level::named("earth").unwrap())
+        .add_plugin(HeadlessPlugin)
+        .insert_resource(clock);
+    app.world_mut()
+        .get_resource_mut::<GameRng>()
+        .unwrap()
+        .set_fixed_seed(1);
+    app.app
+}
+
+fn montociel(app: &mut App) -> Entity {
+    app.world
+        .query_filtered::<Entity, With<Montociel>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+}
+
+fn earth(app: &mut App) -> Entity {
+    app.world
+        .query_filtered::<Entity, With<Earth>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+}
+
+fn force(app: &App, entity: Entity) -> Vec2 {
+    app.world
+        .get::<RigidBodyForces>(entity)
+        .unwrap()
+        .force
+        .into()
+}
+
+fn mass(app: &App, entity: Entity) -> f32 {
+    app.world.get::<RigidBodyMassProps>(entity).unwrap().mass()
+}
+
+fn linvel(app: &App, entity: Entity) -> Vec2 {
+    app.world
+        .get::<RigidBodyVelocity>(entity)
+        .unwrap()
+        .linvel
+        .into()
+}
+
+fn set_linvel(app: &mut App, entity: Entity, linvel: Vec2) {
+    app.world
+        .get_mut::<RigidBodyVelocity>(entity)
+        .unwrap()
+        .linvel = linvel.into();
+}
+
+fn touch(app: &mut App, entity1: Entity, entity2: Entity) {
+    app.world
+        .get_resource_mut::<Events<ContactEvent>>()
+        .unwrap()
+        .send(ContactEvent::Started(entity1.handle(), entity2.handle()));
+}
+
+/// The state transitions queued by a tick happen on the next frame
+fn enter_queued_state(app: &mut App) {
+    app.update();
+}
+
+fn state(app: &App) -> AppState {
+    app.world
+        .get_resource::<State<AppState>>()
+        .unwrap()
+        .current()
+        .clone()
+}
+
+fn score(app: &App) -> u32 {
+    app.world.get_resource::<Score>().unwrap().get()
+}
+
+/// Clouds in play
+fn cloud_count(app: &mut App) -> usize {
+    app.world
+        .query::<&InPlay>()
+        .iter(&app.world)
+        .filter(|in_play| in_play.0)
+        .count()
+}
+
+fn in_play(app: &App, entity: Entity) -> bool {
+    app.world.get::<InPlay>(entity).unwrap().0
+}
+
+/// Retry, as the game over and pause menus do
+fn restart(app: &mut App) {
+    app.world
+        .get_resource_mut::<State<AppState>>()
+        .unwrap()
+        .replace(AppState::InGame)
+        .unwrap();
+    app.update();
+}
+
+fn pool_len(app: &App) -> usize {
+    app.world.get_resource::<CloudPool>().unwrap().len()
+}
+
+/// Park the clouds taken out of play, the pool is off by default
+fn enable_pool(app: &mut App) {
+    app.world.insert_resource(CloudPool::enabled());
+}
+
+#[test]
+fn touching_a_cloud_parks_it_scores_and_jumps() {
+    let mut app = frozen_run();
+    enable_pool(&mut app);
+    let sheep = montociel(&mut app);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+    set_linvel(&mut app, sheep, Vec2::ZERO);
+
+    touch(&mut app, sheep, cloud);
+    app.update();
+
+    assert!(!in_play(&app, cloud));
+    assert_eq!(pool_len(&app), 1);
+    assert_eq!(score(&app), 1);
+    assert_eq!(
+        app.world.get_resource::<RunStats>().unwrap().clouds_bounced,
+        1
+    );
+    // the sheep was still, all its speed comes from the jump,
+    // thrown straight up as the frozen contact has no normal
+    let tuning = app.world.get_resource::<Tuning>().unwrap().clone();
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let pos = app.world.get::<RigidBodyPosition>(sheep).unwrap().position;
+    let expected = Vec2::new(pos.translation.x, pos.translation.y) * tuning.jump_power / scale;
+    assert!((linvel(&app, sheep) - expected).length() < 0.001);
+    assert_eq!(state(&app), AppState::InGame);
+}
+
+#[test]
+fn holding_a_thrust_aims_the_bounce() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+    set_linvel(&mut app, sheep, Vec2::ZERO);
+    app.world
+        .get_resource_mut::<Input<KeyCode>>()
+        .unwrap()
+        .press(KeyCode::D);
+
+    touch(&mut app, sheep, cloud);
+    app.update();
+
+    // halfway between straight up and the clockwise tangent
+    let pos = app.world.get::<RigidBodyPosition>(sheep).unwrap().position;
+    let up = Vec2::new(pos.translation.x, pos.translation.y).normalize();
+    let clockwise = Vec2::new(up.y, -up.x);
+    let direction = linvel(&app, sheep).normalize();
+    let expected = (up + clockwise).normalize();
+    assert!(
+        (direction - expected).length() < 0.05,
+        "{:?} {:?}",
+        direction,
+        expected
+    );
+}
+
+#[test]
+fn the_bounce_follows_the_contact_normal() {
+    let mut app = live_tuned(
+        Tuning {
+            cloud_belt_count: 0,
+            new_cloud_count: 0,
+            ..Default::default()
+        },
+        SimulationClock::Lockstep,
+    );
+    // enter the run
+    app.update();
+    let tuning = app.world.get_resource::<Tuning>().unwrap().clone();
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let sheep = montociel(&mut app);
+    // the sheep falls on the upper right of a cloud, far from straight above it
+    let sheep_position = Vec2::new(
+        0.,
+        (tuning.earth_radius + 3. * tuning.montociel_radius) / scale,
+    );
+    let gap = 0.1;
+    let distance = (tuning.montociel_radius + tuning.cloud_radius) / scale + gap;
+    let offset = Vec2::new(0.6, 0.8);
+    *app.world.get_mut::<RigidBodyPosition>(sheep).unwrap() = sheep_position.into();
+    *app.world.get_mut::<RigidBodyVelocity>(sheep).unwrap() = RigidBodyVelocity::default();
+    let cloud_position = sheep_position - offset * distance;
+    app.world
+        .spawn()
+        .insert_bundle(RigidBodyBundle {
+            body_type: RigidBodyType::KinematicVelocityBased,
+            position: cloud_position.into(),
+            ..Default::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape: ColliderShape::ball(tuning.cloud_radius / scale),
+            flags: ActiveEvents::CONTACT_EVENTS.into(),
+            ..Default::default()
+        })
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true));
+
+    let bounced = |app: &App| app.world.get_resource::<RunStats>().unwrap().clouds_bounced;
+    for _ in 0..60 {
+        app.update();
+        if bounced(&app) > 0 {
+            break;
+        }
+    }
+
+    assert_eq!(bounced(&app), 1);
+    // from the cloud center to the sheep, not straight up as a bounce without normal
+    let position: Vec2 = app
+        .world
+        .get::<RigidBodyPosition>(sheep)
+        .unwrap()
+        .position
+        .translation
+        .vector
+        .into();
+    let normal = (position - cloud_position).normalize();
+    let direction = linvel(&app, sheep).normalize();
+    assert!(normal.x > 0.5, "{:?}", normal);
+    assert!(
+        (direction - normal).length() < 0.1,
+        "{:?} {:?}",
+        direction,
+        normal
+    );
+}
+
+#[test]
+fn touching_the_earth_ends_the_run() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let earth = earth(&mut app);
+
+    touch(&mut app, earth, sheep);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::GameOver);
+    assert_eq!(
+        app.world.get_resource::<RunStats>().unwrap().death_cause,
+        Some(DeathCause::Earth)
+    );
+}
+
+#[test]
+fn touching_an_evil_cloud_ends_the_run() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(Evil)
+        .id();
+
+    touch(&mut app, sheep, cloud);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::GameOver);
+    assert_eq!(
+        app.world.get_resource::<RunStats>().unwrap().death_cause,
+        Some(DeathCause::EvilCloud)
+    );
+    assert_eq!(score(&app), 0);
+}
+
+#[test]
+fn two_fatal_contacts_in_a_tick_end_the_run_once() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let earth = earth(&mut app);
+    let evil = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(Evil)
+        .id();
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+
+    touch(&mut app, sheep, evil);
+    touch(&mut app, sheep, earth);
+    touch(&mut app, sheep, cloud);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::GameOver);
+    assert_eq!(
+        app.world.get_resource::<RunStats>().unwrap().death_cause,
+        Some(DeathCause::EvilCloud)
+    );
+    // nothing counts after the hit that ended the run
+    assert_eq!(score(&app), 0);
+}
+
+#[test]
+fn power_up_clouds_start_their_effect() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let doubler = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .insert(CloudKind::ScoreDoubler)
+        .id();
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+
+    touch(&mut app, sheep, doubler);
+    app.update();
+    touch(&mut app, sheep, cloud);
+    app.update();
+
+    let power_ups = app.world.get_resource::<PowerUps>().unwrap();
+    assert!(power_ups.is_active(CloudKind::ScoreDoubler));
+    assert!(!power_ups.is_active(CloudKind::Shield));
+    // both bounces are doubled
+    assert_eq!(score(&app), 4);
+}
+
+#[test]
+fn the_shield_takes_one_evil_hit() {
+    let mut app = frozen_run();
+    enable_pool(&mut app);
+    let sheep = montociel(&mut app);
+    app.world
+        .get_resource_mut::<PowerUps>()
+        .unwrap()
+        .activate(CloudKind::Shield);
+    let evil_clouds: Vec<Entity> = (0..2)
+        .map(|_| {
+            app.world
+                .spawn()
+                .insert(Cloud(Vec2::ZERO))
+                .insert(InPlay(true))
+                .insert(Evil)
+                .id()
+        })
+        .collect();
+
+    touch(&mut app, sheep, evil_clouds[0]);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::InGame);
+    assert!(!in_play(&app, evil_clouds[0]));
+    assert!(!app
+        .world
+        .get_resource::<PowerUps>()
+        .unwrap()
+        .is_active(CloudKind::Shield));
+
+    touch(&mut app, sheep, evil_clouds[1]);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::GameOver);
+}
+
+#[test]
+fn a_hit_costs_a_life_and_knocks_the_sheep_back() {
+    let mut app = frozen_tuned(
+        Level::named("earth").unwrap(),
+        Tuning {
+            cloud_belt_count: 0,
+            lives: 2,
+            invulnerability: 0.5,
+            ..Default::default()
+        },
+    );
+    let sheep = montociel(&mut app);
+    let sheep_position: Vec2 = app
+        .world
+        .get::<RigidBodyPosition>(sheep)
+        .unwrap()
+        .position
+        .translation
+        .vector
+        .into();
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(Evil)
+        .insert(RigidBodyPosition::from(sheep_position - Vec2::new(0., 1.)))
+        .id();
+
+    touch(&mut app, sheep, cloud);
+    app.update();
+    enter_queued_state(&mut app);
+
+    assert_eq!(state(&app), AppState::InGame);
+    assert_eq!(app.world.get_resource::<Lives>().unwrap().0, 1);
+    assert!(app.world.get::<Invulnerable>(sheep).is_some());
+    let knockback = app.world.get_resource::<Tuning>().unwrap().knockback;
+    let direction = linvel(&app, sheep).normalize();
+    assert!((direction - Vec2::Y).length() < 0.1, "{:?}", direction);
+    assert!(linvel(&app, sheep).length() > knockback / 2.);
+
+    // hits while invulnerable only push the sheep away
+    touch(&mut app, sheep, cloud);
+    app.update();
+    enter_queued_state(&mut app);
+    assert_eq!(state(&app), AppState::InGame);
+
+    for _ in 0..30 {
+        app.update();
+    }
+    assert!(app.world.get::<Invulnerable>(sheep).is_none());
+    touch(&mut app, sheep, cloud);
+    app.update();
+    enter_queued_state(&mut app);
+    assert_eq!(state(&app), AppState::GameOver);
+}
+
+#[test]
+fn speed_is_clamped() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let max_speed = app.world.get_resource::<Tuning>().unwrap().max_speed;
+
+    set_linvel(&mut app, sheep, Vec2::new(1000., -500.));
+    app.update();
+
+    let speed = linvel(&app, sheep).length();
+    assert!((speed - max_speed).abs() < 0.001, "speed {}", speed);
+}
+
+#[test]
+fn a_dash_goes_over_the_speed_cap_and_spends_stamina() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let tuning = app.world.get_resource::<Tuning>().unwrap().clone();
+    let pos = app.world.get::<RigidBodyPosition>(sheep).unwrap().position;
+    let clockwise = Vec2::new(pos.translation.y, -pos.translation.x).normalize();
+    set_linvel(&mut app, sheep, clockwise * tuning.max_speed);
+
+    let press_dash = |app: &mut App| {
+        let mut keys = app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+        keys.release(KeyCode::Space);
+        app.update();
+        let mut keys = app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+        keys.press(KeyCode::Space);
+        app.update();
+    };
+    press_dash(&mut app);
+
+    assert!(linvel(&app, sheep).length() > tuning.max_speed);
+    let stamina = app.world.get::<Dash>(sheep).unwrap().stamina;
+    assert!((stamina - (1. - tuning.dash_cost)).abs() < 0.001);
+
+    // cooling down
+    press_dash(&mut app);
+    let stamina = app.world.get::<Dash>(sheep).unwrap().stamina;
+    assert!((stamina - (1. - tuning.dash_cost)).abs() < 0.001);
+
+    // bounces refill the stamina
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+    touch(&mut app, sheep, cloud);
+    app.update();
+    let stamina = app.world.get::<Dash>(sheep).unwrap().stamina;
+    assert!((stamina - (1. - tuning.dash_cost + tuning.dash_refill)).abs() < 0.001);
+}
+
+#[test]
+fn the_earth_pulls_toward_its_center() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let gravity = app.world.get_resource::<Tuning>().unwrap().gravity;
+
+    app.update();
+
+    let position: Vec2 = app
+        .world
+        .get::<RigidBodyPosition>(sheep)
+        .unwrap()
+        .position
+        .translation
+        .vector
+        .into();
+    let expected = -position.normalize() * gravity * mass(&app, sheep);
+    assert!((force(&app, sheep) - expected).length() < 0.01);
+}
+
+#[test]
+fn gravity_wells_add_up() {
+    let well = |x: f32| WellLayout {
+        position: Vec2::new(x, 150.),
+        size: 1.,
+        strength: 1.,
+        law: Some(GravityLaw::InverseLinear),
+        lethal: false,
+    };
+    // the sheep starts at (150, 150) pixels, right between the two wells
+    let mut app = frozen_level(Level {
+        wells: vec![well(-150.), well(450.)],
+    });
+    let sheep = montociel(&mut app);
+    assert_eq!(
+        app.world.query::<&GravityWell>().iter(&app.world).count(),
+        2
+    );
+
+    app.update();
+
+    assert!(force(&app, sheep).length() < 0.01);
+}
+
+#[test]
+fn gravity_laws_weaken_away_from_the_surface() {
+    let laws = [
+        GravityLaw::Constant,
+        GravityLaw::InverseLinear,
+        GravityLaw::InverseSquare { softening: 0.5 },
+    ];
+    for law in laws {
+        assert!((law.factor(1.) - 1.).abs() < 0.0001, "{:?}", law);
+    }
+    assert_eq!(GravityLaw::Constant.factor(4.), 1.);
+    assert!((GravityLaw::InverseLinear.factor(4.) - 0.25).abs() < 0.0001);
+    let inverse_square = GravityLaw::InverseSquare { softening: 0.5 };
+    assert!((inverse_square.factor(4.) - 1.25 / 16.25).abs() < 0.0001);
+    // finite at the center
+    assert!((inverse_square.factor(0.) - 5.).abs() < 0.0001);
+}
+
+#[test]
+fn drag_does_not_depend_on_the_step() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let dt = app
+        .world
+        .get_resource::<IntegrationParameters>()
+        .unwrap()
+        .dt;
+
+    set_linvel(&mut app, sheep, Vec2::new(10., 0.));
+    app.update();
+    let one_step = linvel(&app, sheep);
+
+    app.world
+        .get_resource_mut::<IntegrationParameters>()
+        .unwrap()
+        .dt = dt / 2.;
+    set_linvel(&mut app, sheep, Vec2::new(10., 0.));
+    app.update();
+    app.update();
+    let two_half_steps = linvel(&app, sheep);
+
+    assert!(one_step.x < 10.);
+    assert!((one_step - two_half_steps).length() < 0.001);
+}
+
+#[test]
+fn a_late_frame_catches_up_with_several_ticks() {
+    let mut app = frozen_run();
+    let dt = app
+        .world
+        .get_resource::<IntegrationParameters>()
+        .unwrap()
+        .dt;
+    let elapsed = |app: &App| app.world.get_resource::<Difficulty>().unwrap().elapsed;
+    let before = elapsed(&app);
+
+    // three ticks behind, plus the duration of the frame itself
+    app.world
+        .insert_resource(SimulationClock::RealTime { lag: 3. * dt });
+    app.update();
+
+    let ticks = ((elapsed(&app) - before) / dt).round() as u32;
+    assert!((3..=5).contains(&ticks), "{} ticks", ticks);
+}
+
+#[test]
+fn a_run_does_not_depend_on_the_frame_rate() {
+    let mut batched = live_run(SimulationClock::RealTime { lag: 0. });
+    let dt = batched
+        .world
+        .get_resource::<IntegrationParameters>()
+        .unwrap()
+        .dt;
+    for _ in 0..100 {
+        // a few ticks behind each frame, clouds spawn in the middle of frames
+        batched
+            .world
+            .insert_resource(SimulationClock::RealTime { lag: 3.5 * dt });
+        batched.update();
+    }
+    let elapsed = batched.world.get_resource::<Difficulty>().unwrap().elapsed;
+    let ticks = (elapsed / dt).round() as usize;
+    assert!(ticks >= 300, "{} ticks", ticks);
+
+    let mut lockstep = live_run(SimulationClock::Lockstep);
+    for _ in 0..ticks {
+        lockstep.update();
+    }
+
+    let position = |app: &mut App| {
+        let sheep = montociel(app);
+        Vec2::from(
+            app.world
+                .get::<RigidBodyPosition>(sheep)
+                .unwrap()
+                .position
+                .translation
+                .vector,
+        )
+    };
+    let bounced = |app: &App| app.world.get_resource::<RunStats>().unwrap().clouds_bounced;
+    assert!(bounced(&lockstep) > 0);
+    assert_eq!(bounced(&batched), bounced(&lockstep));
+    assert_eq!(score(&batched), score(&lockstep));
+    assert_eq!(cloud_count(&mut batched), cloud_count(&mut lockstep));
+    assert_eq!(position(&mut batched), position(&mut lockstep));
+}
+
+#[test]
+fn clouds_spawn_each_period() {
+    let mut app = frozen_run();
+    let tuning = app.world.get_resource::<Tuning>().unwrap().clone();
+    let dt = app
+        .world
+        .get_resource::<IntegrationParameters>()
+        .unwrap()
+        .dt;
+    let ticks_per_period = (tuning.new_cloud_period / dt).round() as usize;
+    assert_eq!(cloud_count(&mut app), 0);
+
+    for period in 1..=3 {
+        for _ in 0..ticks_per_period {
+            app.update();
+        }
+        assert_eq!(
+            cloud_count(&mut app),
+            period * tuning.new_cloud_count as usize
+        );
+    }
+}
+
+#[test]
+fn clouds_leaving_the_play_area_are_despawned() {
+    let mut app = frozen_run();
+    let radius = app.world.get_resource::<Tuning>().unwrap().play_area_radius;
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::X))
+        .insert(InPlay(true))
+        .insert(RigidBodyPosition::from(Vec2::new(1.1 * radius / scale, 0.)))
+        .id();
+
+    app.update();
+
+    assert!(app.world.get_entity(cloud).is_none());
+    assert_eq!(pool_len(&app), 0);
+}
+
+#[test]
+fn clouds_leaving_the_play_area_are_parked() {
+    let mut app = frozen_run();
+    enable_pool(&mut app);
+    let radius = app.world.get_resource::<Tuning>().unwrap().play_area_radius;
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let mut spawn_cloud_at = |distance: f32| {
+        app.world
+            .spawn()
+            .insert(Cloud(Vec2::X))
+            .insert(InPlay(true))
+            .insert(RigidBodyPosition::from(Vec2::new(distance, 0.)))
+            .id()
+    };
+    let inside = spawn_cloud_at(0.9 * radius / scale);
+    let outside = spawn_cloud_at(1.1 * radius / scale);
+
+    app.update();
+
+    assert!(in_play(&app, inside));
+    assert!(!in_play(&app, outside));
+    assert_eq!(pool_len(&app), 1);
+    // the earth is a cloud too, it stays in the middle
+    let earth = earth(&mut app);
+    assert!(app.world.get_entity(earth).is_some());
+}
+
+#[test]
+fn a_cloud_touched_as_it_leaves_is_parked_once() {
+    let mut app = frozen_run();
+    enable_pool(&mut app);
+    let sheep = montociel(&mut app);
+    let radius = app.world.get_resource::<Tuning>().unwrap().play_area_radius;
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::X))
+        .insert(InPlay(true))
+        .insert(RigidBodyPosition::from(Vec2::new(1.1 * radius / scale, 0.)))
+        .id();
+
+    touch(&mut app, sheep, cloud);
+    app.update();
+
+    assert!(!in_play(&app, cloud));
+    assert_eq!(pool_len(&app), 1);
+}
+
+#[test]
+fn the_play_area_surrounds_each_well() {
+    let mut app = frozen_level(Level::named("binary").unwrap());
+    let radius = app.world.get_resource::<Tuning>().unwrap().play_area_radius;
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    let well = app
+        .world
+        .query::<&GravityWell>()
+        .iter(&app.world)
+        .map(|well| well.position)
+        .find(|position| position.x > 0.)
+        .unwrap();
+    // out of the play area around the origin, but not around the well
+    let position = well + well.normalize() * 0.9 * radius / scale;
+    assert!(position.length() > radius / scale);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::X))
+        .insert(InPlay(true))
+        .insert(RigidBodyPosition::from(position))
+        .id();
+
+    app.update();
+
+    assert!(in_play(&app, cloud));
+}
+
+#[test]
+fn clouds_follow_their_pattern() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let sheep_position: Vec2 = app
+        .world
+        .get::<RigidBodyPosition>(sheep)
+        .unwrap()
+        .position
+        .translation
+        .vector
+        .into();
+    let mut spawn_cloud = |position: Vec2, pattern: CloudPattern| {
+        app.world
+            .spawn()
+            .insert(Cloud(Vec2::new(0., -3.)))
+            .insert(pattern)
+            .insert(InPlay(true))
+            .insert(RigidBodyPosition::from(position))
+            .id()
+    };
+    let orbiting = spawn_cloud(
+        Vec2::new(10., 0.),
+        CloudPattern::Orbit {
+            center: Vec2::ZERO,
+            clockwise: false,
+            time_left: 10.,
+        },
+    );
+    let homing = spawn_cloud(
+        sheep_position + Vec2::new(-10., 0.),
+        CloudPattern::Homing { time_left: 10. },
+    );
+
+    app.update();
+
+    let velocity = |app: &App, entity: Entity| app.world.get::<Cloud>(entity).unwrap().0;
+    // counterclockwise around the earth, at the same speed
+    assert!((velocity(&app, orbiting) - Vec2::new(0., 3.)).length() < 0.001);
+    // turning a little toward the sheep, on its right
+    let turned = velocity(&app, homing);
+    assert!((turned.length() - 3.).abs() < 0.001);
+    assert!(turned.x > 0. && turned.x < 0.1, "{:?}", turned);
+}
+
+#[test]
+fn parked_clouds_are_reused() {
+    let mut app = frozen_run();
+    enable_pool(&mut app);
+    {
+        // only good clouds, so the parked ones fit any new cloud
+        let mut tuning = app.world.get_resource_mut::<Tuning>().unwrap();
+        tuning.evil_chance_per_point = 0.;
+        tuning.evil_chance_per_second = 0.;
+    }
+    let tuning = app.world.get_resource::<Tuning>().unwrap().clone();
+    let dt = app
+        .world
+        .get_resource::<IntegrationParameters>()
+        .unwrap()
+        .dt;
+    let ticks_per_period = (tuning.new_cloud_period / dt).round() as usize;
+    for _ in 0..ticks_per_period {
+        app.update();
+    }
+    let clouds: Vec<Entity> = app
+        .world
+        .query_filtered::<Entity, With<InPlay>>()
+        .iter(&app.world)
+        .collect();
+    assert_eq!(clouds.len(), tuning.new_cloud_count as usize);
+
+    let scale = app
+        .world
+        .get_resource::<RapierConfiguration>()
+        .unwrap()
+        .scale;
+    for &cloud in &clouds {
+        *app.world.get_mut::<RigidBodyPosition>(cloud).unwrap() =
+            Vec2::new(2. * tuning.play_area_radius / scale, 0.).into();
+    }
+    app.update();
+    assert_eq!(cloud_count(&mut app), 0);
+    assert_eq!(pool_len(&app), clouds.len());
+
+    for _ in 0..ticks_per_period {
+        app.update();
+    }
+    // the same entities are back in play, none was spawned
+    assert_eq!(cloud_count(&mut app), clouds.len());
+    assert_eq!(pool_len(&app), 0);
+    for &cloud in &clouds {
+        assert!(in_play(&app, cloud));
+    }
+    let entities = app
+        .world
+        .query_filtered::<Entity, With<InPlay>>()
+        .iter(&app.world)
+        .count();
+    assert_eq!(entities, clouds.len());
+}
+
+#[test]
+fn restarting_resets_the_score() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .id();
+    touch(&mut app, sheep, cloud);
+    app.update();
+    assert_eq!(score(&app), 1);
+
+    let earth = earth(&mut app);
+    touch(&mut app, sheep, earth);
+    app.update();
+    enter_queued_state(&mut app);
+    assert_eq!(state(&app), AppState::GameOver);
+
+    restart(&mut app);
+
+    assert_eq!(state(&app), AppState::InGame);
+    assert_eq!(score(&app), 0);
+    let stats = app.world.get_resource::<RunStats>().unwrap();
+    assert_eq!(stats.clouds_bounced, 0);
+    assert_eq!(stats.death_cause, None);
+}
+
+#[test]
+fn restarting_despawns_only_the_last_run() {
+    let mut app = frozen_run();
+    let sheep = montociel(&mut app);
+    let earth = earth(&mut app);
+    let cloud = app
+        .world
+        .spawn()
+        .insert(Cloud(Vec2::ZERO))
+        .insert(InPlay(true))
+        .insert(RunEntity)
+        .id();
+    let child = app.world.spawn().id();
+    app.world.entity_mut(cloud).push_children(&[child]);
+    let outsider = app.world.spawn().id();
+
+    touch(&mut app, sheep, earth);
+    app.update();
+    enter_queued_state(&mut app);
+    // the run stays on screen behind the game over
+    assert_eq!(state(&app), AppState::GameOver);
+    assert!(app.world.get_entity(sheep).is_some());
+
+    restart(&mut app);
+
+    for entity in [sheep, earth, cloud, child] {
+        assert!(app.world.get_entity(entity).is_none());
+    }
+    assert!(app.world.get_entity(outsider).is_some());
+    assert_ne!(montociel(&mut app), sheep);
+}
+
+#[test]
+fn tunings_the_game_would_crash_on_are_rejected() {
+    assert!(Tuning::default().validate().is_ok());
+    let empty_speeds = Tuning {
+        new_cloud_min_speed: 5.,
+        new_cloud_max_speed: 5.,
+        ..Default::default()
+    };
+    assert!(empty_speeds.validate().is_err());
+    let still_belt = Tuning {
+        cloud_belt_max_speed: 0.,
+        ..Default::default()
+    };
+    assert!(still_belt.validate().is_err());
+    let clouds_at_the_center = Tuning {
+        new_cloud_distance: 0.,
+        ..Default::default()
+    };
+    assert!(clouds_at_the_center.validate().is_err());
+    let no_earth = Tuning {
+        earth_radius: f32::NAN,
+        ..Default::default()
+    };
+    assert!(no_earth.validate().is_err());
+}
+
+#[test]
+fn the_evil_chance_stays_a_probability() {
+    let difficulty = Difficulty { elapsed: 10. };
+    let score = Score::default();
+    let too_high = Tuning {
+        base_evil_chance: 3.,
+        max_evil_chance: 2.,
+        ..Default::default()
+    };
+    assert_eq!(difficulty.evil_chance(&too_high, &score), 1.);
+    let no_cap = Tuning {
+        max_evil_chance: -1.,
+        ..Default::default()
+    };
+    assert_eq!(difficulty.evil_chance(&no_cap, &score), 0.);
+    let not_a_number = Tuning {
+        max_evil_chance: f32::NAN,
+        ..Default::default()
+    };
+    assert_eq!(difficulty.evil_chance(&not_a_number, &score), 0.);
+    assert!(too_high.validate().is_err());
+    assert!(no_cap.validate().is_err());
+    assert!(not_a_number.validate().is_err());
+}
+
+#[test]
+fn laps_are_counted_around_the_main_well() {
+    // the origin is empty space between the two wells
+    let mut app = frozen_level(Level::named("binary").unwrap());
+    let sheep = montociel(&mut app);
+    let well = app
+        .world
+        .query::<&GravityWell>()
+        .iter(&app.world)
+        .map(|well| (well.position, well.radius))
+        .find(|(position, _)| position.x > 0.);
+    let (center, radius) = well.unwrap();
+
+    for step in 0..=20 {
+        let angle = step as f32 / 16. * 2. * std::f32::consts::PI;
+        let position = center + Vec2::new(angle.cos(), angle.sin()) * 2. * radius;
+        *app.world.get_mut::<RigidBodyPosition>(sheep).unwrap() = position.into();
+        app.update();
+    }
+
+    assert_eq!(app.world.get_resource::<Score>().unwrap().laps(), 1);
+}
+
+#[test]
+fn the_tuning_file_is_loaded_through_the_asset_server() {
+    let mut app = App::build();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(bevy::asset::AssetPlugin)
+        // not the file values, so the test sees when they replace it
+        .insert_resource(Tuning {
+            gravity: -1.,
+            ..Default::default()
+        })
+        .add_plugin(TuningPlugin);
+    let mut app = app.app;
+
+    for _ in 0..500 {
+        app.update();
+        if app.world.get_resource::<Tuning>().unwrap().gravity >= 0. {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    let loaded = app.world.get_resource::<Tuning>().unwrap();
+    assert_eq!(loaded.gravity, Tuning::default().gravity);
+}